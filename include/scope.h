@@ -0,0 +1,61 @@
+/*
+ * C API of scope-rs, build with `cargo build --release --features ffi`
+ * and link against libscope_rs.so.
+ *
+ * Functions that return int return 0 on success and -1 on error,
+ * see scope_last_error().
+ */
+#ifndef SCOPE_H
+#define SCOPE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ScopeHandle ScopeHandle;
+
+/* Counts of the last run. */
+typedef struct ScopeSummary {
+	size_t crawled;
+	size_t included;
+	size_t excluded;
+	size_t errors;
+	bool cancelled;
+} ScopeSummary;
+
+/* Create a handle with the current directory as root and one job. */
+ScopeHandle *scope_new(void);
+/* Release a handle from scope_new(). */
+void scope_free(ScopeHandle *handle);
+
+/* Crawl root, instead of the current directory. */
+int scope_add_root(ScopeHandle *handle, const char *root);
+/* Skip the paths that match pattern. */
+int scope_add_exclude(ScopeHandle *handle, const char *pattern);
+/* Classify and index with jobs threads. */
+int scope_set_jobs(ScopeHandle *handle, size_t jobs);
+
+/* Create the databases in the current directory. */
+int scope_run(ScopeHandle *handle);
+/* Classify file without indexing it: 1 if it is a source file, 0 if not. */
+int scope_classify(ScopeHandle *handle, const char *file);
+/*
+ * Stop the running scope_run() on handle soon, or the next one. May be called
+ * from another thread, e.g. a signal handling one. The cancelled run returns 0,
+ * its summary is partial.
+ */
+void scope_cancel(const ScopeHandle *handle);
+/* Fill summary with the counts of the last scope_run(). */
+int scope_summary(ScopeHandle *handle, ScopeSummary *summary);
+
+/* The error of the last failed call, or NULL. Valid until the next call. */
+const char *scope_last_error(const ScopeHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SCOPE_H */
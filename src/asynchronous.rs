@@ -0,0 +1,314 @@
+//! The pipeline of `Scope` on a tokio runtime.
+//!
+//! Drivers and indexers are async processes, so that many repositories
+//! can be indexed concurrently on one runtime.
+
+use std::error::Error;
+use std::fs;
+use std::io::IoSlice;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::{Classification, Event, MimeType, Phase, ScanSummary, Scope, Source, WorkerSummary, IDLE_WAIT, WRITE_BATCH, WRITE_BUFFER};
+use crate::tags::line_slices;
+
+/// Errors of the async pipeline can cross tasks.
+pub type AsyncError = Box<dyn Error + Send + Sync>;
+
+/// Number of events buffered for a slow receiver.
+const EVENT_BUFFER: usize = 256;
+
+/// The indexer processes, fed through their stdin.
+struct Indexers {
+    children: Vec<Child>,
+    incremental: Option<bool>,
+}
+
+impl Indexers {
+    /// Spawn the indexers like `TagFileCreatorBuilder::spawn()` does for `Scope::run()`.
+    ///
+    /// An indexer that does not start is a warning,
+    /// it is an error only if none can run or no Exuberant ctags is found.
+    fn spawn(scope: &Scope) -> Result<Self, AsyncError> {
+        let tag_files = scope.tag_files();
+        let incremental = tag_files.cscope_incremental();
+        let mut children = vec![];
+        for (tool, command) in tag_files.commands() {
+            let child = Command::from(command.map_err(|e| format!("Indexers: {}", e))?)
+                .stdin(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn();
+            match child {
+                Ok(child) => children.push(child),
+                Err(_) => scope.reporter.warning(&format!("Cannot run {}.", tool)),
+            }
+        }
+        if children.is_empty() && ! scope.options.backends.is_empty() {
+            return Err("Indexers: Cannot create any tag file database.".into());
+        }
+        Ok(Indexers { children, incremental })
+    }
+
+    /// Write each of `paths` on a line, with a single vectored write to each indexer.
+    async fn write_batch(&mut self, paths: impl IntoIterator<Item = &Path>) -> Result<(), AsyncError> {
+        let lines = line_slices(paths);
+        for child in &mut self.children {
+            let stdin = child.stdin.as_mut().ok_or("Indexer died.")?;
+            let mut slices = &mut lines.clone()[..];
+            while ! slices.is_empty() {
+                match stdin.write_vectored(slices).await? {
+                    0 => return Err("Indexer stopped reading.".into()),
+                    n => IoSlice::advance_slices(&mut slices, n),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Close stdin and wait for the databases.
+    async fn finish(mut self) {
+        for child in &mut self.children {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.flush().await.unwrap_or_default();
+            }
+        }
+        for child in &mut self.children {
+            child.wait().await.ok();
+        }
+    }
+
+    /// Close stdin and give the indexers `timeout` to terminate, kill them afterwards.
+    async fn abort(mut self, timeout: Duration) {
+        for child in &mut self.children {
+            drop(child.stdin.take());
+        }
+        for child in &mut self.children {
+            if tokio::time::timeout(timeout, child.wait()).await.is_err() {
+                child.kill().await.unwrap_or_default();
+            }
+        }
+    }
+}
+
+impl Scope {
+    /// Create the databases on the current tokio runtime.
+    ///
+    /// Like `run()`, with an `Event` for each file and phase sent to `events`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "scope", level = "info", err,
+        skip_all, fields(roots = self.roots.len(), jobs = self.jobs)))]
+    pub async fn run_async(
+        self: Arc<Self>,
+        events: Option<mpsc::Sender<Event>>,
+    ) -> Result<ScanSummary, AsyncError> {
+        let files = Arc::new(self.file_queue(self.jobs));
+        let indexers = Indexers::spawn(&self)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let summary = Arc::new(Mutex::new(ScanSummary::default()));
+
+        let crawler = Arc::new(self.crawler(Arc::clone(&files))); // Producer
+        self.start(&files, &summary);
+
+        if let Some(ref events) = events {
+            events.send(Event::PhaseChanged(Phase::Crawl)).await.ok();
+        }
+        if let Some(ref hooks) = self.hooks {
+            hooks.phase(Phase::Crawl);
+        }
+        let start = Instant::now();
+        let crawl = {
+            let (crawler, running) = (Arc::clone(&crawler), Arc::clone(&running));
+            let (scope, files) = (Arc::clone(&self), Arc::clone(&files));
+            tokio::task::spawn_blocking(move || {
+                let result = scope.crawl(&crawler, &files).map_err(|e| e.to_string());
+                running.store(false, Ordering::Relaxed);
+                result.map(|_| start.elapsed())
+            })
+        };
+
+        // The writer owns the indexers, the workers feed it.
+        let (sources, mut written) = mpsc::channel::<Source>(WRITE_BUFFER);
+        let writer = {
+            let (scope, summary, events) = (Arc::clone(&self), Arc::clone(&summary), events.clone());
+            let writer = async move {
+                let mut indexers = indexers;
+                let mut batch = Vec::with_capacity(WRITE_BATCH);
+                while let Some(source) = written.recv().await {
+                    if scope.cancel.load(Ordering::Relaxed) {
+                        break; // Abandon the channel
+                    }
+                    // Take what else is waiting, up to a batch.
+                    batch.push(source);
+                    while batch.len() < WRITE_BATCH {
+                        let Ok(source) = written.try_recv() else { break };
+                        batch.push(source);
+                    }
+                    let start = Instant::now();
+                    if let Some(ref consumer) = scope.consumer {
+                        for source in &batch {
+                            consumer.consume(&source.shown, source.mime());
+                        }
+                    }
+                    let result = indexers.write_batch(batch.iter().map(|s| s.shown.as_path())).await;
+                    summary.lock().unwrap().write_time += start.elapsed();
+                    let error = result.err().map(|e| e.to_string());
+                    let indexed = ! indexers.children.is_empty();
+                    for source in batch.drain(..) {
+                        let event = scope.written(source, indexed, error.as_deref(), &summary);
+                        if let (Some(events), Some(event)) = (&events, event) {
+                            events.send(event).await.ok();
+                        }
+                    }
+                }
+                indexers
+            };
+            #[cfg(feature = "tracing")]
+            let writer = tracing::Instrument::in_current_span(writer);
+            tokio::spawn(writer)
+        };
+
+        let utilization = Arc::new(Mutex::new(vec![WorkerSummary::default(); self.jobs]));
+        let mut workers = JoinSet::new();
+        for id in 0..self.jobs {
+            let scope = Arc::clone(&self);
+            let queue = files.worker(); // Consumer
+            let sources = sources.clone();
+            let running = Arc::clone(&running);
+            let summary = Arc::clone(&summary);
+            let events = events.clone();
+            let utilization = Arc::clone(&utilization);
+            let worker = async move {
+                // Events of the shared stages, sent once they are done.
+                let mut pending = vec![];
+                let started = Instant::now();
+                let mut worker = WorkerSummary::default();
+                while ! scope.cancel.load(Ordering::Relaxed) {
+                    // Check before popping, the crawler may add a last file in between.
+                    let crawled = ! running.load(Ordering::Relaxed);
+                    let Some(path) = queue.pop() else {
+                        if let Some(ref hooks) = scope.hooks {
+                            hooks.working(id, None);
+                        }
+                        if crawled {
+                            break;
+                        }
+                        let waiting = Instant::now();
+                        tokio::time::sleep(IDLE_WAIT).await;
+                        worker.wait(waiting);
+                        continue;
+                    };
+                    worker.files += 1;
+                    if let Some(ref hooks) = scope.hooks {
+                        hooks.working(id, Some(&path));
+                    }
+                    summary.lock().unwrap().crawled += 1;
+                    let mut emit = |event| if events.is_some() { pending.push(event) };
+                    let source = match scope.recalled(path, &summary, &mut emit) {
+                        Ok(source) => source,
+                        Err(path) => {
+                            let classification = scope.classify_async(&path).await;
+                            scope.classified(path, classification.map_err(|e| e as Box<dyn Error>), &summary, &mut emit)
+                        },
+                    };
+                    let source = source.and_then(|source| scope.deduplicate(source, &summary, &mut emit));
+                    if let Some(ref events) = events {
+                        for event in pending.drain(..) {
+                            events.send(event).await.ok();
+                        }
+                    }
+                    if let Some(source) = source {
+                        if sources.send(source).await.is_err() {
+                            break; // The writer was cancelled
+                        }
+                    }
+                }
+                worker.busy = started.elapsed().saturating_sub(worker.idle);
+                utilization.lock().unwrap()[id] = worker;
+            };
+            #[cfg(feature = "tracing")]
+            let worker = tracing::Instrument::in_current_span(worker);
+            workers.spawn(worker);
+        }
+
+        drop(sources);
+        let crawl_time = crawl.await?;
+        workers.join_all().await;
+        let indexers = writer.await?;
+        let crawl_time = crawl_time?;
+
+        let mut summary = summary.lock().unwrap().clone();
+        summary.workers = utilization.lock().unwrap().clone();
+        summary.crawl_time = crawl_time;
+        summary.classify_time = start.elapsed();
+        summary.excluded_pattern = crawler.excluded();
+        summary.drained = self.drain.load(Ordering::Relaxed);
+        summary.cscope_incremental = indexers.incremental;
+        for (pattern, n) in crawler.exclusions() {
+            summary.exclude("pattern", &pattern, n);
+        }
+
+        if self.cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            indexers.abort(Duration::from_secs(2)).await;
+            if ! self.keep_partial && ! self.options.backends.is_empty() {
+                for database in self.tag_files().databases() {
+                    fs::remove_file(database).unwrap_or_default();
+                }
+            }
+        } else {
+            if let Some(ref events) = events {
+                events.send(Event::PhaseChanged(Phase::Finalize)).await.ok();
+            }
+            if let Some(ref hooks) = self.hooks {
+                hooks.phase(Phase::Finalize);
+            }
+            let start = Instant::now();
+            indexers.finish().await;
+            summary.finalize_time = start.elapsed();
+        }
+
+        if let Some(events) = events {
+            events.send(Event::Done(summary.clone())).await.ok();
+        }
+        Ok(summary)
+    }
+
+    /// Create the databases on a new task and stream the events.
+    ///
+    /// The last event is either `Done` or `Failed`.
+    pub fn stream(self: Arc<Self>) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+        tokio::spawn(async move {
+            if let Err(e) = self.run_async(Some(sender.clone())).await {
+                sender.send(Event::Failed(e.to_string())).await.ok();
+            }
+        });
+        receiver
+    }
+
+    /// Classify `path` like `FileClassifier::classify()`, with an async driver process.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "classify", level = "debug", err,
+        skip_all, fields(path = %path.display())))]
+    async fn classify_async(&self, path: &Path) -> Result<Classification, AsyncError> {
+        let mime = match self.classifier.needs_mime(path) {
+            true => match self.driver.sniff(path) {
+                Some(mime) => Some(mime),
+                None => {
+                    let out = Command::from(self.driver.command(path)?).output().await?;
+                    Some(String::from_utf8(out.stdout)?.parse::<MimeType>()?)
+                },
+            },
+            false => None,
+        };
+        // The error is not Send, so only its message crosses tasks.
+        self.classifier.classify_with(path, || mime.ok_or_else(|| "No MIME type.".into()))
+            .map_err(|e| e.to_string().into())
+    }
+}
@@ -0,0 +1,28 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+/// Log of all decisions of a run, one JSON object per line.
+pub struct AuditLog {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl AuditLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(AuditLog { file: Mutex::new(BufWriter::new(file)) })
+    }
+
+    /// Append `record` to the log.
+    pub fn log(&self, record: &Value) -> io::Result<()> {
+        writeln!(self.file.lock().unwrap(), "{}", record)
+    }
+
+    /// Write out everything logged so far.
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the checkpoint is flushed to disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The path that a record holds as `bytes`, if they are one here.
+///
+/// Paths are any bytes on Unix, elsewhere they are recorded as UTF-8.
+pub fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    #[cfg(unix)]
+    return Some(PathBuf::from(OsStr::from_bytes(bytes)));
+    #[cfg(not(unix))]
+    std::str::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Log of classified files, so that an interrupted build can resume.
+///
+/// The first record is the fingerprint of the settings that decided, see
+/// `Scan::fingerprint()`. Each other record is the language of an included
+/// file, or `-` for an excluded one, a tab and the path, terminated by NUL.
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<(BufWriter<File>, Instant)>,
+}
+
+impl Checkpoint {
+    /// Name of the checkpoint for the databases with an optional `name`.
+    pub fn path(name: Option<&str>) -> PathBuf {
+        match name {
+            Some(name) => PathBuf::from(format!(".scope.{}.checkpoint", name)),
+            None => PathBuf::from(".scope.checkpoint"),
+        }
+    }
+
+    /// Read the decisions of a previous run, `None` if it had other settings
+    /// than the `fingerprint`.
+    ///
+    /// Included files map to their language, excluded ones to `None`.
+    pub fn load(path: &Path, fingerprint: &str) -> io::Result<Option<HashMap<PathBuf, Option<String>>>> {
+        let content = fs::read(path)?;
+        let mut result = HashMap::new();
+        // A record cut short by the interruption lacks its terminator.
+        let end = content.iter().rposition(|b| *b == 0).unwrap_or(0);
+        let mut records = content[..end].split(|b| *b == 0);
+        if records.next() != Some(fingerprint.as_bytes()) {
+            return Ok(None);
+        }
+        for record in records {
+            let Some(tab) = record.iter().position(|b| *b == b'\t') else {
+                continue;
+            };
+            let language = String::from_utf8_lossy(&record[..tab]);
+            let language = (language != "-").then(|| language.into_owned());
+            if let Some(file) = path_from_bytes(&record[tab + 1..]) {
+                result.insert(file, language);
+            }
+        }
+        Ok(Some(result))
+    }
+
+    /// Start a checkpoint for the settings with the `fingerprint`, `append`
+    /// to an existing one with the same when resuming.
+    pub fn create(path: &Path, append: bool, fingerprint: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(! append)
+            .open(path)?;
+        let mut writer = BufWriter::new(file);
+        if ! append {
+            writer.write_all(fingerprint.as_bytes())?;
+            writer.write_all(b"\0")?;
+        }
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            file: Mutex::new((writer, Instant::now())),
+        })
+    }
+
+    /// Record the decision for `file`, with the `language` of included files.
+    pub fn record(&self, file: &Path, language: Option<&str>) -> io::Result<()> {
+        let mut guard = self.file.lock().unwrap();
+        let (writer, flushed) = &mut *guard;
+        write!(writer, "{}\t", language.unwrap_or("-"))?;
+        writer.write_all(file.as_os_str().as_encoded_bytes())?;
+        writer.write_all(b"\0")?;
+        if flushed.elapsed() >= FLUSH_INTERVAL {
+            writer.flush()?;
+            *flushed = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Remove the checkpoint after a complete run.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use scope_rs::{Context, IoNice, Rule, Schedule};
+
+/// Name of the project-local configuration file.
+pub const CONFIG_FILE: &str = ".scope.toml";
+
+/// Names of the presets for common project types.
+pub const PRESETS: &[&str] = &["kernel", "embedded", "web", "rust"];
+
+/// Project-local configuration from a `.scope.toml` file.
+///
+/// All values are optional. Command line flags take precedence.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub driver: Option<String>,
+    pub verbose: Option<Verbosity>,
+    pub quiet: Option<bool>,
+    pub jobs: Option<usize>,
+    pub crawl_threads: Option<usize>,
+    pub schedule: Option<Schedule>,
+    pub queue_limit: Option<usize>,
+    pub sniff_below: Option<u64>,
+    pub driver_helpers: Option<usize>,
+    pub dedup: Option<bool>,
+    pub preflight: Option<bool>,
+    pub nice: Option<i32>,
+    pub ionice: Option<IoNice>,
+    pub excludes: Vec<String>,
+    pub languages: Option<Vec<String>>,
+    pub per_root: Option<bool>,
+    pub preset: Option<String>,
+    pub kernel_mode: Option<bool>,
+    pub ctags_args: Option<Vec<String>>,
+    /// Extensions per language, replacing the built-in ones of the language.
+    pub extensions: BTreeMap<String, Vec<String>>,
+    /// Start from an empty extension table instead of the built-in one.
+    pub default_extensions: Option<bool>,
+    /// MIME types per language, replacing the built-in ones of the language.
+    pub mimetypes: BTreeMap<String, Vec<String>>,
+    /// Start from an empty MIME type table instead of the built-in one.
+    pub default_mimetypes: Option<bool>,
+    /// Extensions of files that are never source, replacing the built-in ones.
+    pub binary_extensions: Option<Vec<String>>,
+    /// Classification rules, checked in order before the tables.
+    pub rules: Vec<Rule>,
+}
+
+/// Verbosity, either a level like the number of `-v` flags
+/// or `true` for the per-file output of `-vv`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Verbosity {
+    Flag(bool),
+    Level(u8),
+}
+
+impl Verbosity {
+    pub fn level(self) -> u8 {
+        match self {
+            Verbosity::Flag(true) => 2,
+            Verbosity::Flag(false) => 0,
+            Verbosity::Level(level) => level,
+        }
+    }
+}
+
+impl Config {
+    /// Read a configuration file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Configuration for a common project type, see `PRESETS`.
+    pub fn preset(name: &str) -> Result<Self, Box<dyn Error>> {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let config = match name {
+            "kernel" => Config {
+                excludes: strings(&["/Documentation/", "/scripts/", "/tools/", "/samples/"]),
+                languages: Some(strings(&["c", "asm"])),
+                kernel_mode: Some(true),
+                ctags_args: Some(strings(&["--c-kinds=+p", "--langmap=c:+.h"])),
+                ..Config::default()
+            },
+            "embedded" => Config {
+                excludes: strings(&["/build/", "/out/"]),
+                languages: Some(strings(&["c", "c++", "asm"])),
+                kernel_mode: Some(true),
+                ctags_args: Some(strings(&["--c-kinds=+p"])),
+                ..Config::default()
+            },
+            "web" => Config {
+                excludes: strings(&["/node_modules/", "/dist/", "/vendor/", ".min.js"]),
+                languages: Some(strings(&["javascript", "php", "python", "ruby"])),
+                ..Config::default()
+            },
+            "rust" => Config {
+                excludes: strings(&["/target/"]),
+                languages: Some(strings(&["rust"])),
+                ..Config::default()
+            },
+            _ => return Err(format!("Unknown preset '{}', use one of: {}.",
+                name, PRESETS.join(", ")).into()),
+        };
+        Ok(config)
+    }
+
+    /// Find the nearest configuration file, starting at `root` and walking upward.
+    pub fn discover(root: &Path) -> Result<Option<(PathBuf, Self)>, Box<dyn Error>> {
+        let root = root.canonicalize().with_context(|| root.display())?;
+        for dir in root.ancestors() {
+            let path = dir.join(CONFIG_FILE);
+            if path.is_file() {
+                let config = Config::load(&path)?;
+                return Ok(Some((path, config)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Merge `other` under `self`.
+    ///
+    /// Values already set in `self` win, excludes and rules are accumulated.
+    /// The rules of `self` come first.
+    pub fn merge(&mut self, other: Config) {
+        self.driver = self.driver.take().or(other.driver);
+        self.verbose = self.verbose.or(other.verbose);
+        self.quiet = self.quiet.or(other.quiet);
+        self.jobs = self.jobs.or(other.jobs);
+        self.crawl_threads = self.crawl_threads.or(other.crawl_threads);
+        self.schedule = self.schedule.or(other.schedule);
+        self.queue_limit = self.queue_limit.or(other.queue_limit);
+        self.sniff_below = self.sniff_below.or(other.sniff_below);
+        self.driver_helpers = self.driver_helpers.or(other.driver_helpers);
+        self.dedup = self.dedup.or(other.dedup);
+        self.preflight = self.preflight.or(other.preflight);
+        self.nice = self.nice.or(other.nice);
+        self.ionice = self.ionice.take().or(other.ionice);
+        self.per_root = self.per_root.or(other.per_root);
+        self.languages = self.languages.take().or(other.languages);
+        self.preset = self.preset.take().or(other.preset);
+        self.kernel_mode = self.kernel_mode.or(other.kernel_mode);
+        self.ctags_args = self.ctags_args.take().or(other.ctags_args);
+        self.default_extensions = self.default_extensions.or(other.default_extensions);
+        for (language, extensions) in other.extensions {
+            self.extensions.entry(language).or_insert(extensions);
+        }
+        self.default_mimetypes = self.default_mimetypes.or(other.default_mimetypes);
+        for (language, mimetypes) in other.mimetypes {
+            self.mimetypes.entry(language).or_insert(mimetypes);
+        }
+        self.binary_extensions = self.binary_extensions.take().or(other.binary_extensions);
+        self.rules.extend(other.rules);
+        for x in other.excludes {
+            if ! self.excludes.contains(&x) {
+                self.excludes.push(x);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::exit::Exit;
+
+/// Reasons to stop a run early, set by signal handlers and timers.
+#[derive(Default)]
+pub struct Control {
+    stop: Arc<AtomicBool>,
+    interrupted: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    drain: Arc<AtomicBool>,
+}
+
+impl Control {
+    /// Stop on SIGINT, drain on SIGTERM.
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let control = Control::default();
+        // A second Ctrl-C terminates immediately.
+        signal_hook::flag::register_conditional_shutdown(SIGINT, Exit::Interrupted as i32,
+            Arc::clone(&control.interrupted))?;
+        signal_hook::flag::register(SIGINT, Arc::clone(&control.interrupted))?;
+        signal_hook::flag::register(SIGINT, Arc::clone(&control.stop))?;
+        // So does a second SIGTERM.
+        signal_hook::flag::register_conditional_shutdown(SIGTERM, Exit::Drained as i32,
+            Arc::clone(&control.drain))?;
+        signal_hook::flag::register(SIGTERM, Arc::clone(&control.drain))?;
+        Ok(control)
+    }
+
+    /// Drain after `max_runtime`.
+    pub fn set_max_runtime(&self, max_runtime: Duration) {
+        let drain = Arc::clone(&self.drain);
+        let timed_out = Arc::clone(&self.timed_out);
+        thread::spawn(move || {
+            thread::sleep(max_runtime);
+            timed_out.store(true, Ordering::Relaxed);
+            drain.store(true, Ordering::Relaxed);
+        });
+    }
+
+    /// Flag that is set once the run should stop, for whatever reason.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Flag that is set once crawling should stop, while the files
+    /// found so far are still indexed.
+    pub fn drain_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.drain)
+    }
+
+    /// Whether the run was drained.
+    pub fn drained(&self) -> bool {
+        self.drain.load(Ordering::Relaxed)
+    }
+
+    /// Whether the run was interrupted by the user.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Whether the run exceeded its maximum runtime.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+}
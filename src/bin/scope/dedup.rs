@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Included files by content, so that only one of a set of byte-identical
+/// files is indexed, like the vendored copies of a library.
+///
+/// Files are only hashed once another file of the same size turns up.
+#[derive(Default)]
+pub struct Dedup {
+    /// The first file of each size, and its hash once it was needed.
+    sizes: Mutex<HashMap<u64, (PathBuf, Option<blake3::Hash>)>>,
+    /// The first file with each size and hash.
+    hashes: Mutex<HashMap<(u64, blake3::Hash), PathBuf>>,
+}
+
+impl Dedup {
+    fn hash(file: &Path) -> io::Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(File::open(file)?)?;
+        Ok(hasher.finalize())
+    }
+
+    /// The file that `file` has the same content as, if one came first.
+    pub fn original(&self, file: &Path) -> io::Result<Option<PathBuf>> {
+        let size = file.metadata()?.len();
+        let (first, first_hash) = {
+            let mut sizes = self.sizes.lock().unwrap();
+            match sizes.get(&size) {
+                Some((first, hash)) => (first.clone(), *hash),
+                None => {
+                    sizes.insert(size, (file.to_path_buf(), None));
+                    return Ok(None);
+                },
+            }
+        };
+        // The first file of a size is hashed once the second one comes.
+        let first_hash = first_hash.or_else(|| {
+            let hash = Dedup::hash(&first).ok()?;
+            self.sizes.lock().unwrap().entry(size).and_modify(|(_, h)| *h = Some(hash));
+            Some(hash)
+        });
+        let hash = Dedup::hash(file)?;
+        let mut hashes = self.hashes.lock().unwrap();
+        if let Some(first_hash) = first_hash {
+            hashes.entry((size, first_hash)).or_insert(first);
+        }
+        match hashes.entry((size, hash)) {
+            Entry::Occupied(original) => Ok(Some(original.get().clone())),
+            Entry::Vacant(entry) => {
+                entry.insert(file.to_path_buf());
+                Ok(None)
+            },
+        }
+    }
+}
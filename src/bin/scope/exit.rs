@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+use std::process::ExitCode;
+
+/// Exit status of scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Everything went fine.
+    Success = 0,
+    /// Any error not covered below.
+    Failure = 1,
+    /// Invalid command line arguments.
+    Usage = 2,
+    /// No usable MIME driver found.
+    NoDriver = 3,
+    /// Neither cscope nor ctags are available.
+    NoIndexer = 4,
+    /// Some files could not be classified or indexed.
+    Partial = 5,
+    /// The maximum runtime was exceeded.
+    TimedOut = 6,
+    /// The run was interrupted.
+    Interrupted = 130,
+    /// The run was drained by SIGTERM, the databases have the files found before.
+    Drained = 143,
+}
+
+impl Exit {
+    /// Make an error that exits with this status.
+    pub fn error<E: fmt::Display>(self, err: E) -> Box<dyn Error> {
+        Box::new(ExitError { exit: self, message: err.to_string() })
+    }
+
+    /// Find the exit status for an error, or the error it has context for.
+    pub fn of(err: &(dyn Error + 'static)) -> Exit {
+        std::iter::successors(Some(err), |&e| e.source())
+            .find_map(|e| e.downcast_ref::<ExitError>())
+            .map_or(Exit::Failure, |e| e.exit)
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> ExitCode {
+        ExitCode::from(exit as u8)
+    }
+}
+
+/// An error with a specific exit status.
+#[derive(Debug)]
+struct ExitError {
+    exit: Exit,
+    message: String,
+}
+
+impl fmt::Display for ExitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ExitError {}
+
+/// Exit status documentation for the help text.
+pub const EXIT_STATUS: &str = "\
+Exit status:
+  0    Success
+  1    Failure
+  2    Invalid arguments
+  3    No usable MIME driver found
+  4    Neither cscope nor ctags available
+  5    Some files could not be classified or indexed
+  6    Maximum runtime exceeded
+  130  Interrupted
+  143  Drained by SIGTERM";
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use core_affinity::CoreId;
+
+use scope_rs::{Decided, Driver, DriverList, Hooks, Phase, Recall};
+
+use crate::audit::AuditLog;
+use crate::checkpoint::Checkpoint;
+use crate::dedup::Dedup;
+use crate::progress::{Events, Progress};
+use crate::scaling::Scaler;
+use crate::state::State;
+use crate::stats::Stats;
+
+/// The stages of `build` and `inspect` that `Scope` leaves to its hooks.
+///
+/// Decisions go to the checkpoint, the state, the audit log and the
+/// inspection, and earlier ones come back from them.
+pub struct BuildHooks {
+    /// The drivers with the settings of the inspection, to print decisions.
+    pub driver: Arc<DriverList>,
+    /// The name of the databases, for the phase events.
+    pub name: Option<String>,
+    /// Print each included file, as `-vv` does.
+    pub verbose: bool,
+    /// Print each directory that is scanned, as `-v` does.
+    pub directories: bool,
+    /// Count the files and lines per language.
+    pub language_stats: bool,
+    pub checkpoint: Option<Checkpoint>,
+    pub state: Option<State>,
+    /// The decisions of the interrupted run that is resumed.
+    pub resumed: HashMap<PathBuf, Option<String>>,
+    pub dedup: Option<Dedup>,
+    pub audit: Option<Arc<AuditLog>>,
+    pub events: Option<Arc<Events>>,
+    pub stats: Arc<Stats>,
+    pub progress: Arc<Progress>,
+    pub scaler: Option<Arc<Scaler>>,
+    /// Cores to pin the workers to, in turn.
+    pub cores: Vec<CoreId>,
+}
+
+impl BuildHooks {
+    /// Record the decision for `path` for the next runs.
+    fn record(&self, path: &Path, language: Option<&str>) {
+        if let Some(ref checkpoint) = self.checkpoint {
+            checkpoint.record(path, language).unwrap_or_default();
+        }
+        if let Some(ref state) = self.state {
+            state.record(path, language).unwrap_or_default();
+        }
+    }
+
+    /// Write out the checkpoint and the state of a complete run.
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(checkpoint) = self.checkpoint {
+            checkpoint.finish()?;
+        }
+        if let Some(state) = self.state {
+            state.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Hooks for BuildHooks {
+    fn phase(&self, phase: Phase) {
+        if let Some(ref events) = self.events {
+            let phase = match phase {
+                Phase::Crawl => "crawl",
+                Phase::Finalize => "finalize",
+            };
+            events.phase(phase, self.name.as_deref());
+        }
+    }
+
+    fn worker(&self, id: usize) {
+        if ! self.cores.is_empty() {
+            core_affinity::set_for_current(self.cores[id % self.cores.len()]);
+        }
+    }
+
+    fn admit(&self, id: usize, done: &dyn Fn() -> bool) -> bool {
+        match self.scaler {
+            Some(ref scaler) => scaler.admit(id, done),
+            None => true,
+        }
+    }
+
+    fn working(&self, id: usize, path: Option<&Path>) {
+        self.progress.set(id, path);
+        if let Some(path) = path.filter(|path| self.directories && path.is_dir()) {
+            eprintln!("Scanning {}", path.display());
+        }
+    }
+
+    fn recall(&self, path: &Path) -> Option<Recall> {
+        if let Some(language) = self.resumed.get(path) {
+            return Some(Recall { language: language.clone(), unchanged: false });
+        }
+        let language = self.state.as_ref()?.unchanged(path)?;
+        Some(Recall { language: language.map(str::to_string), unchanged: true })
+    }
+
+    fn original(&self, path: &Path) -> Option<PathBuf> {
+        self.dedup.as_ref()?.original(path).ok().flatten()
+    }
+
+    fn send(&self, send: &mut dyn FnMut() -> bool) -> bool {
+        match self.scaler {
+            Some(ref scaler) => scaler.send(send),
+            None => send(),
+        }
+    }
+
+    fn decided(&self, decided: &Decided) {
+        if let Some(reason) = decided.reason {
+            self.driver.inspect(reason, decided.shown, decided.mime, self.verbose && reason.include());
+        }
+        if self.language_stats && decided.rule != "duplicate" {
+            if let Some(language) = decided.language {
+                self.stats.language(language, decided.shown);
+            }
+        }
+        // Files that failed are decided anew next time.
+        if decided.rule != "error" {
+            self.record(decided.path, decided.language);
+        }
+        if let Some(ref audit) = self.audit {
+            let record = serde_json::json!({
+                "path": decided.shown.to_string_lossy(),
+                "decision": if decided.language.is_some() { "include" } else { "exclude" },
+                "rule": decided.rule,
+                "mime": decided.mime,
+                "driver": decided.mime.map(|_| self.driver.name()),
+                "language": decided.language,
+                "index": decided.index.map(|index| index.err().unwrap_or("ok")),
+            });
+            audit.log(&record).unwrap_or_default();
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+
+/// Name of the lock file next to the databases.
+pub const LOCK_FILE: &str = ".scope.lock";
+
+/// Advisory lock on the databases in the current directory.
+///
+/// The lock is released when dropped. The file itself stays, removing it
+/// would race with other runs waiting for it.
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Lock `path`, `wait` for other runs to release it or fail right away.
+    pub fn acquire(path: &Path, wait: bool) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) if ! wait => {
+                return Err(format!("{}: Another scope run is in progress, \
+                    use --wait or --no-lock", path.display()).into());
+            },
+            Err(TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another scope run to finish...");
+                file.lock().map_err(|e| format!("{}: {}", path.display(), e))?;
+            },
+            Err(TryLockError::Error(e)) => return Err(format!("{}: {}", path.display(), e).into()),
+        }
+        Ok(Lock { _file: file })
+    }
+}
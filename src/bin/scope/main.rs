@@ -0,0 +1,1560 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+extern crate clap;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap_mangen::Man;
+
+mod audit;
+use audit::AuditLog;
+mod checkpoint;
+use checkpoint::Checkpoint;
+mod state;
+use state::State;
+mod dedup;
+use dedup::Dedup;
+mod control;
+use control::Control;
+mod config;
+use config::{Config, PRESETS};
+mod exit;
+use exit::{Exit, EXIT_STATUS};
+mod hooks;
+use hooks::BuildHooks;
+mod lock;
+use lock::{Lock, LOCK_FILE};
+mod progress;
+use progress::{Events, Metrics, Progress};
+mod scaling;
+use scaling::Scaler;
+mod stats;
+use stats::Stats;
+#[cfg(feature = "tui")]
+mod tui;
+
+use scope_rs::{
+    Action,
+    Classification,
+    ClassifierConfig,
+    Context,
+    ContextError,
+    DEFAULT_EXCLUDES,
+    Driver,
+    DriverList,
+    FileClassifier,
+    FileCrawler,
+    FileQueue,
+    Format,
+    IoNice,
+    PathStyle,
+    Pattern,
+    Priority,
+    Reason,
+    Rule,
+    SNIFF_SIZE,
+    Schedule,
+    Scope,
+    TagFileCreator,
+};
+
+
+/// Parse a verbosity level from the environment.
+///
+/// `true` is the per-file output of `-vv`, as before levels existed.
+fn verbose_parser(level: &str) -> Result<u8, String> {
+    match level {
+        "true" => Ok(2),
+        "false" => Ok(0),
+        _ => level.parse().map_err(|e| format!("{}", e)),
+    }
+}
+
+/// Parse jobs parameter into a `usize`.
+///
+/// The validation includes that there is at least one job.
+fn jobs_parser(jobs: &str) -> Result<usize, clap::error::Error> {
+    let err = clap::error::Error::new(clap::error::ErrorKind::ValueValidation);
+    match jobs.parse::<usize>() {
+        Ok(result) => {
+            if result > 0 {
+                Ok(result)
+            } else {
+                Err(err)
+            }
+        },
+        _ => Err(err),
+    }
+}
+
+/// Check an exclude, so that an invalid one fails before the crawl.
+fn exclude_parser(exclude: &str) -> Result<String, String> {
+    Pattern::exclude(exclude).map(|_| exclude.to_string())
+}
+
+/// Parse the number of index jobs, *auto* for up to one per core that
+/// adapt to the bottleneck. *auto* is 0 until `apply_config()`.
+fn index_jobs_parser(jobs: &str) -> Result<usize, clap::error::Error> {
+    match jobs {
+        "auto" => Ok(0),
+        _ => jobs_parser(jobs),
+    }
+}
+
+/// Parse a point in time, either RFC 3339 or a duration ago like `2h`.
+fn since_parser(since: &str) -> Result<SystemTime, String> {
+    if let Ok(time) = humantime::parse_rfc3339_weak(since) {
+        return Ok(time);
+    }
+    match humantime::parse_duration(since) {
+        Ok(ago) => SystemTime::now().checked_sub(ago)
+            .ok_or_else(|| format!("'{}' is too long ago.", since)),
+        Err(e) => Err(format!("'{}' is neither RFC 3339 nor a duration: {}", since, e)),
+    }
+}
+
+/// Command line arguments.
+#[derive(Parser, Debug)]
+#[command(name = "scope", author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+/// Subcommands, `build` is the default.
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Create ctags and cscope databases (default).
+    Build(BuildArgs),
+
+    /// Test files and print if they would be scoped.
+    /// This also prints the driver in use.
+    Inspect(InspectArgs),
+
+    /// Explain why a file would be scoped or not.
+    Why(WhyArgs),
+
+    /// Browse the decisions for all files interactively and try out
+    /// which languages to scope.
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+
+    /// List all MIME drivers in order of preference.
+    /// This is the same as `--driver list`.
+    Drivers(DriversArgs),
+
+    /// List all supported languages with their extensions and MIME types,
+    /// as configured in `.scope.toml`.
+    Languages(LanguagesArgs),
+
+    /// Only update the ctags entries of a single file.
+    /// The file is decided as `build` decides it. The cscope database is
+    /// not touched, the next `build` refreshes it.
+    /// This is compatible with editor plugins like vim-gutentags.
+    Update(UpdateArgs),
+
+    /// Remove the databases created by `build`.
+    Clean(CleanArgs),
+
+    /// Inspect the configuration.
+    Config(ConfigArgs),
+
+    /// Print the man page.
+    Man(ManArgs),
+}
+
+/// Arguments for all subcommands that crawl directories.
+#[derive(clap::Args, Debug, Clone)]
+struct ScanArgs {
+    /// Specify a *driver* from `scope drivers` as MIME driver,
+    /// *list* lists them.
+    #[arg(short, long, env = "SCOPE_DRIVER")]
+    driver: Option<OsString>,
+
+    /// Run in verbose mode, `-v` reports each directory on stderr,
+    /// `-vv` also prints each included file.
+    #[arg(short, long, action = ArgAction::Count, value_parser = verbose_parser,
+        env = "SCOPE_VERBOSE")]
+    verbose: u8,
+
+    /// Terminate printed file names with NUL instead of newline.
+    #[arg(short = '0', long, action, default_value_t = false, env = "SCOPE_PRINT0")]
+    print0: bool,
+
+    /// Print and index absolute paths.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_ABSOLUTE")]
+    absolute: bool,
+
+    /// Print and index paths relative to *dir*.
+    #[arg(long, value_name = "DIR", conflicts_with = "absolute", env = "SCOPE_RELATIVE_TO")]
+    relative_to: Option<PathBuf>,
+
+    /// Only consider files modified after *time*, given in RFC 3339 format
+    /// or as a duration ago, e.g. *2024-05-01T08:00:00Z* or *8h*.
+    #[arg(long, value_name = "TIME", value_parser = since_parser, env = "SCOPE_SINCE")]
+    since: Option<SystemTime>,
+
+    /// Continue an interrupted build. Classification decisions are taken
+    /// from the checkpoint of the previous run instead of the drivers.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_RESUME")]
+    resume: bool,
+
+    /// Only classify files that changed since the last complete build with
+    /// *--state*, by size, modification time or content. The decisions of
+    /// the others are taken from *.scope.state*.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_STATE")]
+    state: bool,
+
+    /// Index only one of a set of files with the same content, like
+    /// vendored copies. Files of the same size are compared by hash.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_DEDUP")]
+    dedup: bool,
+
+    /// Stop crawling after *duration*, e.g. *10min*, and finish the databases
+    /// with the files found so far.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration,
+        env = "SCOPE_MAX_RUNTIME")]
+    max_runtime: Option<Duration>,
+
+    /// Keep incomplete databases when interrupted instead of removing them.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_KEEP_PARTIAL")]
+    keep_partial: bool,
+
+    /// Count the files before the build, so that the progress reports show
+    /// a percentage. With *--state*, the last build tells instead.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_PREFLIGHT")]
+    preflight: bool,
+
+    /// Create a FIFO at *path* that reports the progress to each reader,
+    /// like SIGUSR1 does on stderr.
+    #[arg(long, value_name = "PATH", env = "SCOPE_STATUS_FIFO")]
+    status_fifo: Option<PathBuf>,
+
+    /// Write every decision to *path* as JSON lines, for auditing
+    /// and comparing runs.
+    #[arg(long, value_name = "PATH", env = "SCOPE_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Write progress events as JSON lines to *path*, e.g. a FIFO or /dev/fd/3.
+    #[arg(long, value_name = "PATH", env = "SCOPE_PROGRESS_JSON")]
+    progress_json: Option<PathBuf>,
+
+    /// Write metrics in the Prometheus text format to *path* every second,
+    /// e.g. for the textfile collector of node_exporter.
+    #[arg(long, value_name = "PATH", env = "SCOPE_METRICS_FILE")]
+    metrics_file: Option<PathBuf>,
+
+    /// Print the excluded files grouped by rule and MIME type or pattern
+    /// at the end of the run.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_REPORT")]
+    report: bool,
+
+    /// Print the number of files and lines per language and the utilization
+    /// of each job at the end of the run.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_STATS")]
+    stats: bool,
+
+    /// Print the time spent crawling, in the drivers, in the classification
+    /// rules and writing to the indexers at the end of the run.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_TIMINGS")]
+    timings: bool,
+
+    /// Do not print a summary at the end of the run.
+    #[arg(short, long, action, default_value_t = false, env = "SCOPE_QUIET")]
+    quiet: bool,
+
+    /// Number of parallel jobs to classify files. *auto* runs up to one per
+    /// core and parks those that only wait for cscope and ctags.
+    #[arg(short, long = "index-jobs", visible_alias = "jobs", action,
+        // SAFETY: unwrap() does not panic with known-good value in constructor.
+        default_value_t = thread::available_parallelism()
+                                .unwrap_or(NonZeroUsize::new(1).unwrap()).get(),
+        value_parser = index_jobs_parser,
+        env = "SCOPE_JOBS",
+    )]
+    jobs: usize,
+
+    /// The jobs were *auto*.
+    #[arg(skip)]
+    auto_jobs: bool,
+
+    /// Number of threads to crawl the directories. Keep it low on spinning disks.
+    #[arg(long, action, default_value_t = 1, value_parser = jobs_parser,
+        env = "SCOPE_CRAWL_THREADS")]
+    crawl_threads: usize,
+
+    /// Pin each job to a core, round-robin. May help spawn-heavy builds on
+    /// NUMA machines.
+    #[arg(long, action, default_value_t = false, env = "SCOPE_PIN_THREADS")]
+    pin_threads: bool,
+
+    /// Order in which files are indexed. Interrupted runs keep the files
+    /// indexed first.
+    #[arg(long, value_name = "ORDER", default_value = "fifo",
+        value_parser = PossibleValuesParser::new(Schedule::NAMES)
+            .try_map(|s| s.parse::<Schedule>()),
+        env = "SCOPE_SCHEDULE",
+    )]
+    schedule: Schedule,
+
+    /// Keep at most this many queued files in memory and spill the others to
+    /// a temporary file. Unlimited by default.
+    #[arg(long, value_name = "FILES", value_parser = jobs_parser, env = "SCOPE_QUEUE_LIMIT")]
+    queue_limit: Option<usize>,
+
+    /// Tell the MIME type of files below *bytes* from their content, without
+    /// running the driver, if it is obvious: empty files, binaries and scripts.
+    /// 0 always runs the driver, as do `inspect`, `why` and `tui` with a named driver.
+    #[arg(long, value_name = "BYTES", default_value_t = SNIFF_SIZE, env = "SCOPE_SNIFF_BELOW")]
+    sniff_below: u64,
+
+    /// Keep *n* long-lived driver processes that classify one file after the
+    /// other, instead of starting one per file. One per job by default, 0
+    /// starts one per file. Only `file` can serve several files.
+    #[arg(long, value_name = "N", env = "SCOPE_DRIVER_HELPERS")]
+    driver_helpers: Option<usize>,
+
+    #[command(flatten)]
+    priority: PriorityArgs,
+
+    /// Files and directories to exclude.
+    #[arg(short = 'x', long, value_delimiter = ',', value_parser = exclude_parser, env = "SCOPE_EXCLUDES")]
+    excludes: Option<Vec<String>>,
+
+    /// Always index files matching these globs, whatever their extension
+    /// or MIME type.
+    #[arg(long, value_name = "GLOB", value_delimiter = ',', env = "SCOPE_FORCE_INCLUDE")]
+    force_include: Vec<Pattern>,
+
+    /// Never index files matching these globs, this wins over --force-include.
+    #[arg(long, value_name = "GLOB", value_delimiter = ',', env = "SCOPE_FORCE_EXCLUDE")]
+    force_exclude: Vec<Pattern>,
+
+    /// Only scope files of these languages, see `scope languages`.
+    #[arg(short, long, value_delimiter = ',', env = "SCOPE_LANGUAGES")]
+    languages: Option<Vec<String>>,
+
+    /// Start with the settings for a common project type.
+    /// Explicit flags and .scope.toml take precedence.
+    #[arg(long, value_name = "PRESET", value_parser = PossibleValuesParser::new(PRESETS),
+        env = "SCOPE_PRESET")]
+    preset: Option<String>,
+
+    /// Run cscope in kernel mode, which ignores /usr/include. On by default.
+    #[arg(long, value_name = "BOOL", env = "SCOPE_KERNEL_MODE")]
+    kernel_mode: Option<bool>,
+
+    /// Extra arguments for ctags.
+    #[arg(long, value_name = "ARGS", value_delimiter = ',', allow_hyphen_values = true,
+        env = "SCOPE_CTAGS_ARGS")]
+    ctags_args: Option<Vec<String>>,
+
+    /// Scan directories even if they are as broad as `/` or `$HOME`.
+    #[arg(long, default_value_t = false, env = "SCOPE_FORCE")]
+    force: bool,
+
+    /// Do not look for .scope.toml files above the directories.
+    #[arg(long, default_value_t = false, env = "SCOPE_NO_CONFIG")]
+    no_config: bool,
+
+    #[arg(last = true, default_value = ".")]
+    dir: Vec<PathBuf>,
+}
+
+/// Arguments for the `build` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct BuildArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+
+    /// Create a separate set of databases for each directory,
+    /// named after the directory.
+    #[arg(long, default_value_t = false, env = "SCOPE_PER_ROOT")]
+    per_root: bool,
+
+    #[command(flatten)]
+    lock: LockArgs,
+}
+
+/// Arguments for the `drivers` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct DriversArgs {
+    /// Output format, *json* adds usability and a probe run of each driver.
+    #[arg(long, default_value = "text", env = "SCOPE_FORMAT",
+        value_parser = PossibleValuesParser::new(["text", "json"])
+            .try_map(|s| s.parse::<Format>()),
+    )]
+    format: Format,
+}
+
+/// Arguments for subcommands that write the databases.
+#[derive(clap::Args, Debug, Clone)]
+struct LockArgs {
+    /// Wait for another scope run in the same directory to finish
+    /// instead of failing.
+    #[arg(long, default_value_t = false, env = "SCOPE_WAIT")]
+    wait: bool,
+
+    /// Do not lock the databases against concurrent runs.
+    #[arg(long, default_value_t = false, conflicts_with = "wait", env = "SCOPE_NO_LOCK")]
+    no_lock: bool,
+}
+
+impl LockArgs {
+    /// Take the lock on the databases unless locking is disabled.
+    fn acquire(&self) -> Result<Option<Lock>, Box<dyn Error>> {
+        match self.no_lock {
+            true => Ok(None),
+            false => Lock::acquire(Path::new(LOCK_FILE), self.wait).map(Some),
+        }
+    }
+}
+
+/// Arguments for the `inspect` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct InspectArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+
+    /// Colorize the output. *auto* colorizes for terminals,
+    /// unless NO_COLOR is set.
+    #[arg(long, value_name = "WHEN", default_value = "auto", env = "SCOPE_COLOR",
+        value_parser = ["auto", "always", "never"],
+    )]
+    color: String,
+
+    /// Output format, *json* prints one object per file.
+    #[arg(long, default_value = "text", env = "SCOPE_FORMAT",
+        value_parser = PossibleValuesParser::new(["text", "json"])
+            .try_map(|s| s.parse::<Format>()),
+    )]
+    format: Format,
+
+    /// Only print excluded files.
+    #[arg(long, visible_alias = "inspect-excluded", conflicts_with = "included",
+        env = "SCOPE_EXCLUDED")]
+    excluded: bool,
+
+    /// Only print included files.
+    #[arg(long, visible_alias = "inspect-included", env = "SCOPE_INCLUDED")]
+    included: bool,
+}
+
+impl InspectArgs {
+    /// Which decisions to print, all for `None`.
+    fn show(&self) -> Option<bool> {
+        match (self.included, self.excluded) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Arguments for the `why` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct WhyArgs {
+    /// File to explain.
+    file: PathBuf,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+/// Arguments for the `languages` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct LanguagesArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+/// Arguments for the `tui` subcommand.
+#[cfg(feature = "tui")]
+#[derive(clap::Args, Debug, Clone)]
+struct TuiArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+/// Arguments for the `config` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommands,
+}
+
+/// Subcommands of `config`.
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommands {
+    /// Print the effective configuration of a build with the origin
+    /// of each value: flag, environment, .scope.toml, preset or default.
+    Show(BuildArgs),
+}
+
+/// Arguments for the `man` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct ManArgs {
+    /// Write man pages for scope and all subcommands into *dir*
+    /// instead of printing the one for scope.
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+}
+
+/// Scheduling priority of spawned processes.
+#[derive(clap::Args, Debug, Clone)]
+struct PriorityArgs {
+    /// Niceness of spawned indexer and driver processes.
+    #[arg(long, allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19),
+        env = "SCOPE_NICE",
+    )]
+    nice: Option<i32>,
+
+    /// I/O scheduling of spawned indexer and driver processes.
+    /// One of *idle*, *best-effort[:level]* or *realtime[:level]*.
+    #[arg(long, value_name = "CLASS[:LEVEL]", env = "SCOPE_IONICE")]
+    ionice: Option<IoNice>,
+}
+
+impl PriorityArgs {
+    fn priority(&self) -> Priority {
+        Priority { nice: self.nice, ionice: self.ionice }
+    }
+}
+
+/// Arguments for the `update` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct UpdateArgs {
+    /// File to update.
+    file: PathBuf,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+
+    /// Tags file to update.
+    #[arg(short = 'f', long, default_value = "tags", env = "SCOPE_TAG_FILE")]
+    tag_file: PathBuf,
+
+    #[command(flatten)]
+    lock: LockArgs,
+}
+
+/// Arguments for the `clean` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+struct CleanArgs {
+    /// Remove the databases of each directory, see `build --per-root`.
+    #[arg(long, default_value_t = false, env = "SCOPE_PER_ROOT")]
+    per_root: bool,
+
+    #[command(flatten)]
+    lock: LockArgs,
+
+    #[arg(last = true, default_value = ".")]
+    dir: Vec<PathBuf>,
+}
+
+/// Make a list of excludes from an optional list of excludes.
+///
+/// Also add the default list of excludes to the result.
+fn make_excludes(excludes: Option<Vec<String>>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    if let Some(x) = excludes {
+        result = x;
+    }
+    for x in DEFAULT_EXCLUDES {
+        result.push((**x).to_string());
+    }
+
+    result
+}
+
+/// Collect the nearest configuration file of each directory.
+fn discover_config(dirs: &[PathBuf]) -> Result<Config, Box<dyn Error>> {
+    let mut result = Config::default();
+    for dir in dirs {
+        if let Some((_, config)) = Config::discover(dir)? {
+            result.merge(config);
+        }
+    }
+    Ok(result)
+}
+
+/// Apply the configuration for `dirs` and the preset to `args`.
+///
+/// Flags win over the environment, `.scope.toml` and the preset, in that order.
+fn configure(
+    args: &mut ScanArgs,
+    matches: &ArgMatches,
+    dirs: &[PathBuf],
+) -> Result<Config, Box<dyn Error>> {
+    let mut config = match args.no_config {
+        true => Config::default(),
+        false => discover_config(dirs)?,
+    };
+    if let Some(preset) = args.preset.clone().or(config.preset.clone()) {
+        config.merge(Config::preset(&preset)?);
+    }
+    apply_config(args, matches, &config)?;
+    Ok(config)
+}
+
+/// The classification tables with the changes from `config`.
+fn classifier_config(args: &ScanArgs, config: &Config) -> ClassifierConfig {
+    let mut classifier = ClassifierConfig::default();
+    if config.default_extensions == Some(false) {
+        classifier.extensions.clear();
+        classifier.heuristics.clear();
+    }
+    for (language, extensions) in &config.extensions {
+        let extensions = extensions.iter()
+            .map(|e| e.trim_start_matches('.').to_string())
+            .collect();
+        classifier.set_extensions(&language.to_lowercase(), extensions);
+    }
+    if config.default_mimetypes == Some(false) {
+        classifier.mimetypes.clear();
+    }
+    for (language, mimetypes) in &config.mimetypes {
+        classifier.set_mimetypes(&language.to_lowercase(), mimetypes.clone());
+    }
+    if let Some(ref binary) = config.binary_extensions {
+        classifier.binary = binary.iter().map(|e| e.trim_start_matches('.').to_string()).collect();
+    }
+    // Forced globs are the first rules, so they win over the configured ones.
+    let force = |glob: &Pattern, action| Rule { glob: Some(glob.clone()), ..Rule::new(action) };
+    classifier.rules = args.force_exclude.iter().map(|g| force(g, Action::Exclude))
+        .chain(args.force_include.iter().map(|g| force(g, Action::Include)))
+        .chain(config.rules.iter().cloned())
+        .collect();
+    classifier
+}
+
+/// Check if argument `id` was given on the command line or in the environment.
+fn is_set(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
+
+/// The size below which `inspect`, `why` and `tui` sniff files.
+///
+/// With a named driver they show what that driver answers,
+/// unless `--sniff-below` is given as well.
+fn inspect_sniff_below(args: &ScanArgs, matches: &ArgMatches) -> u64 {
+    match args.driver.is_some() && ! is_set(matches, "sniff_below") {
+        true => 0,
+        false => args.sniff_below,
+    }
+}
+
+/// Fill in `config` values for arguments not given on the command line
+/// or in the environment.
+fn apply_config(
+    args: &mut ScanArgs,
+    matches: &ArgMatches,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let unset = |id: &str| ! is_set(matches, id);
+
+    if let (true, Some(driver)) = (unset("driver"), &config.driver) {
+        args.driver = Some(driver.into());
+    }
+    if let (true, Some(verbose)) = (unset("verbose"), config.verbose) {
+        args.verbose = verbose.level();
+    }
+    if let (true, Some(quiet)) = (unset("quiet"), config.quiet) {
+        args.quiet = quiet;
+    }
+    if let (true, Some(jobs)) = (unset("jobs"), config.jobs) {
+        if jobs == 0 {
+            return Err("Configuration needs at least one job.".into());
+        }
+        args.jobs = jobs;
+    }
+    if let (true, Some(threads)) = (unset("crawl_threads"), config.crawl_threads) {
+        if threads == 0 {
+            return Err("Configuration needs at least one crawl thread.".into());
+        }
+        args.crawl_threads = threads;
+    }
+    if let (true, Some(schedule)) = (unset("schedule"), config.schedule) {
+        args.schedule = schedule;
+    }
+    if let (true, Some(limit)) = (unset("queue_limit"), config.queue_limit) {
+        if limit == 0 {
+            return Err("Configuration needs a queue limit of at least one file.".into());
+        }
+        args.queue_limit = Some(limit);
+    }
+    if let (true, Some(bytes)) = (unset("sniff_below"), config.sniff_below) {
+        args.sniff_below = bytes;
+    }
+    if let (true, Some(helpers)) = (unset("driver_helpers"), config.driver_helpers) {
+        args.driver_helpers = Some(helpers);
+    }
+    if let (true, Some(dedup)) = (unset("dedup"), config.dedup) {
+        args.dedup = dedup;
+    }
+    if let (true, Some(preflight)) = (unset("preflight"), config.preflight) {
+        args.preflight = preflight;
+    }
+    if let (true, Some(nice)) = (unset("nice"), config.nice) {
+        args.priority.nice = Some(nice);
+    }
+    if let (true, Some(ionice)) = (unset("ionice"), config.ionice) {
+        args.priority.ionice = Some(ionice);
+    }
+    if let (true, Some(languages)) = (unset("languages"), &config.languages) {
+        args.languages = Some(languages.clone());
+    }
+    if let (true, Some(kernel_mode)) = (unset("kernel_mode"), config.kernel_mode) {
+        args.kernel_mode = Some(kernel_mode);
+    }
+    if let (true, Some(ctags_args)) = (unset("ctags_args"), &config.ctags_args) {
+        args.ctags_args = Some(ctags_args.clone());
+    }
+    if args.jobs == 0 {
+        args.jobs = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        args.auto_jobs = true;
+    }
+    for exclude in &config.excludes {
+        Pattern::exclude(exclude)?;
+    }
+    if ! config.excludes.is_empty() {
+        let mut excludes = args.excludes.take().unwrap_or_default();
+        excludes.extend(config.excludes.iter().cloned());
+        args.excludes = Some(excludes);
+    }
+    Ok(())
+}
+
+/// Print the effective configuration for `args` with the origin of each value.
+///
+/// The output is valid TOML, the origins are comments.
+fn config_show(mut args: BuildArgs, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let dirs = args.scan.dir.clone();
+    let mut files: Vec<(PathBuf, Config)> = vec![];
+    if ! args.scan.no_config {
+        for dir in &dirs {
+            if let Some(found) = Config::discover(dir)? {
+                files.push(found);
+            }
+        }
+    }
+    let config = configure(&mut args.scan, matches, &dirs)?;
+    if ! is_set(matches, "per_root") {
+        args.per_root = config.per_root.unwrap_or(args.per_root);
+    }
+    let preset = args.scan.preset.clone().or(config.preset.clone());
+    let preset_config = preset.as_deref().map(Config::preset).transpose()?.unwrap_or_default();
+
+    // Where the value of `id` comes from, `set` tells if a configuration has it.
+    // Not every configuration value is an argument.
+    let origin = |id: &str, set: &dyn Fn(&Config) -> bool| -> String {
+        let source = match matches.try_contains_id(id) {
+            Ok(_) => matches.value_source(id),
+            Err(_) => None,
+        };
+        match source {
+            Some(ValueSource::CommandLine) => "command line".to_string(),
+            Some(ValueSource::EnvVariable) =>
+                format!("environment SCOPE_{}", id.to_uppercase()),
+            _ => match files.iter().find(|(_, c)| set(c)) {
+                Some((path, _)) => path.display().to_string(),
+                None if set(&preset_config) =>
+                    format!("preset {}", preset.as_deref().unwrap_or_default()),
+                None => "default".to_string(),
+            },
+        }
+    };
+    let show = |key: &str, value: Option<toml::Value>, origin: String| match value {
+        Some(value) => println!("{:<40} # {}", format!("{} = {}", key, value), origin),
+        None => println!("{:<40} # {}", format!("# {} is not set", key), origin),
+    };
+    let strings = |v: &[String]| toml::Value::from(v.to_vec());
+
+    let scan = &args.scan;
+    show("driver", scan.driver.as_ref().map(|d| d.to_string_lossy().as_ref().into()),
+        origin("driver", &|c| c.driver.is_some()));
+    show("verbose", Some(i64::from(scan.verbose).into()),
+        origin("verbose", &|c| c.verbose.is_some()));
+    show("quiet", Some(scan.quiet.into()), origin("quiet", &|c| c.quiet.is_some()));
+    let jobs = match scan.auto_jobs {
+        true => "auto".into(),
+        false => (scan.jobs as i64).into(),
+    };
+    show("jobs", Some(jobs), origin("jobs", &|c| c.jobs.is_some()));
+    show("crawl-threads", Some((scan.crawl_threads as i64).into()),
+        origin("crawl_threads", &|c| c.crawl_threads.is_some()));
+    show("schedule", Some(scan.schedule.to_string().into()),
+        origin("schedule", &|c| c.schedule.is_some()));
+    show("queue-limit", scan.queue_limit.map(|n| (n as i64).into()),
+        origin("queue_limit", &|c| c.queue_limit.is_some()));
+    show("sniff-below", Some((scan.sniff_below as i64).into()),
+        origin("sniff_below", &|c| c.sniff_below.is_some()));
+    show("driver-helpers", Some((scan.driver_helpers.unwrap_or(scan.jobs) as i64).into()),
+        origin("driver_helpers", &|c| c.driver_helpers.is_some()));
+    show("dedup", Some(scan.dedup.into()), origin("dedup", &|c| c.dedup.is_some()));
+    show("preflight", Some(scan.preflight.into()), origin("preflight", &|c| c.preflight.is_some()));
+    show("nice", scan.priority.nice.map(|n| i64::from(n).into()),
+        origin("nice", &|c| c.nice.is_some()));
+    show("ionice", scan.priority.ionice.map(|i| i.to_string().into()),
+        origin("ionice", &|c| c.ionice.is_some()));
+    show("languages", scan.languages.as_deref().map(strings),
+        origin("languages", &|c| c.languages.is_some()));
+    show("per-root", Some(args.per_root.into()),
+        origin("per_root", &|c| c.per_root.is_some()));
+    show("preset", preset.clone().map(Into::into),
+        origin("preset", &|c| c.preset.is_some()));
+    show("kernel-mode", Some(scan.kernel_mode.unwrap_or(true).into()),
+        origin("kernel_mode", &|c| c.kernel_mode.is_some()));
+    show("ctags-args", scan.ctags_args.as_deref().map(strings),
+        origin("ctags_args", &|c| c.ctags_args.is_some()));
+
+    let extensions: toml::Table = config.extensions.iter()
+        .map(|(language, extensions)| (language.clone(), strings(extensions)))
+        .collect();
+    show("extensions", (! extensions.is_empty()).then(|| extensions.into()),
+        origin("extensions", &|c| ! c.extensions.is_empty()));
+    let mimetypes: toml::Table = config.mimetypes.iter()
+        .map(|(language, mimetypes)| (language.clone(), strings(mimetypes)))
+        .collect();
+    show("mimetypes", (! mimetypes.is_empty()).then(|| mimetypes.into()),
+        origin("mimetypes", &|c| ! c.mimetypes.is_empty()));
+    show("binary-extensions", config.binary_extensions.as_deref().map(strings),
+        origin("binary_extensions", &|c| c.binary_extensions.is_some()));
+    let origins: Vec<_> = files.iter()
+        .filter(|(_, c)| ! c.rules.is_empty())
+        .map(|(path, _)| path.display().to_string())
+        .collect();
+    show("rules", (! config.rules.is_empty()).then(|| toml::Value::try_from(&config.rules)).transpose()?,
+        match origins.is_empty() {
+            true => "default".to_string(),
+            false => origins.join(", "),
+        });
+
+    // Excludes accumulate from all sources, name each of them.
+    let mut origins: Vec<String> = vec![];
+    if is_set(matches, "excludes") {
+        origins.push(origin("excludes", &|_| false));
+    }
+    origins.extend(files.iter()
+        .filter(|(_, c)| ! c.excludes.is_empty())
+        .map(|(path, _)| path.display().to_string()));
+    if ! preset_config.excludes.is_empty() {
+        origins.push(format!("preset {}", preset.as_deref().unwrap_or_default()));
+    }
+    origins.push("default".to_string());
+    show("excludes", Some(strings(&make_excludes(scan.excludes.clone()))), origins.join(", "));
+    Ok(())
+}
+
+/// Directories too broad to scan without `--force`.
+const BROAD_ROOTS: &[&str] = &["/", "/home", "/usr", "/var", "/opt", "/etc", "/tmp"];
+
+/// Refuse to scan system roots or the home directory, unless `force`d.
+///
+/// Print what would be scanned instead.
+fn check_roots(dirs: &[PathBuf], force: bool) -> Result<(), Box<dyn Error>> {
+    if force {
+        return Ok(());
+    }
+    let home = env::var_os("HOME")
+        .and_then(|h| PathBuf::from(h).canonicalize().ok());
+    for dir in dirs {
+        let Ok(canonical) = dir.canonicalize() else {
+            continue; // The crawler reports missing directories.
+        };
+        let broad = BROAD_ROOTS.iter().any(|r| canonical == Path::new(r))
+            || home.as_ref().is_some_and(|h| canonical == *h);
+        if ! broad {
+            continue;
+        }
+
+        eprintln!("Would scan {}:", canonical.display());
+        let mut entries: Vec<PathBuf> = fs::read_dir(&canonical)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        entries.sort();
+        for entry in &entries {
+            eprintln!("  {}", entry.display());
+        }
+        return Err(Exit::Usage.error(format!(
+            "Refusing to scan {}, use --force to scan it anyway.", canonical.display())));
+    }
+    Ok(())
+}
+
+/// Name each root after the last component of its canonical path.
+///
+/// Fails if two roots end up with the same name.
+fn root_names(dirs: &[PathBuf]) -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+    let mut result: Vec<(PathBuf, String)> = vec![];
+    for dir in dirs {
+        let canonical = dir.canonicalize().with_context(|| dir.display())?;
+        let name = canonical.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "root".to_string());
+        if result.iter().any(|(_, n)| *n == name) {
+            return Err(format!("Duplicate root name '{}'.", name).into());
+        }
+        result.push((dir.clone(), name));
+    }
+    Ok(result)
+}
+
+/// Settings and outputs shared by the scans of all roots in a run.
+struct Scan<'a> {
+    args: &'a ScanArgs,
+    inspect: bool,
+    driver: Arc<DriverList>,
+    classifier: Arc<FileClassifier>,
+    stats: Arc<Stats>,
+    control: Control,
+    audit: Option<Arc<AuditLog>>,
+    events: Option<Arc<Events>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Scan<'_> {
+    /// Hash of the settings that decide about files: the classifier tables
+    /// and rules, the selected languages, the driver and sniffing.
+    ///
+    /// Decisions of `--resume` and `--state` are only reused with the same.
+    fn fingerprint(&self) -> String {
+        let mut languages = self.args.languages.as_ref()
+            .map(|languages| languages.iter().map(|l| l.to_lowercase()).collect::<Vec<_>>());
+        if let Some(ref mut languages) = languages {
+            languages.sort();
+        }
+        let settings = serde_json::json!({
+            "classifier": self.classifier.config(),
+            "languages": languages,
+            "driver": self.driver.name(),
+            "sniff-below": self.args.sniff_below,
+        });
+        blake3::hash(settings.to_string().as_bytes()).to_hex().to_string()
+    }
+
+    /// The hooks of a run that creates the databases with an optional `name`.
+    fn hooks(&self, name: Option<&str>) -> Result<BuildHooks, Box<dyn Error>> {
+        let Scan { args, inspect, driver, stats, audit, events, .. } = self;
+        let directories = args.verbose >= 1;
+        let fingerprint = self.fingerprint();
+        let checkpoint_path = Checkpoint::path(name);
+        let resumed = match (inspect, args.resume) {
+            (false, true) => Checkpoint::load(&checkpoint_path, &fingerprint).ok().flatten(),
+            _ => None,
+        };
+        if args.resume && resumed.is_none() && checkpoint_path.exists() && directories {
+            eprintln!("Not resuming, {} has other settings", checkpoint_path.display());
+        }
+        let checkpoint = match inspect {
+            false => Some(Checkpoint::create(&checkpoint_path, resumed.is_some(), &fingerprint)?),
+            true => None,
+        };
+        let state = match (inspect, args.state) {
+            (false, true) => Some(State::open(&State::path(name), &fingerprint)
+                .map_err(|e| format!("{}: {}", State::path(name).display(), e))?),
+            _ => None,
+        };
+        if state.as_ref().is_some_and(State::outdated) && directories {
+            eprintln!("Classifying all files, {} has other settings", State::path(name).display());
+        }
+        let cores = match args.pin_threads {
+            true => core_affinity::get_core_ids().unwrap_or_default(),
+            false => vec![],
+        };
+        if args.pin_threads && cores.is_empty() {
+            eprintln!("Cannot pin jobs to cores, they run unpinned.");
+        }
+        Ok(BuildHooks {
+            driver: Arc::clone(driver),
+            name: name.map(str::to_string),
+            verbose: args.verbose >= 2,
+            directories,
+            language_stats: args.stats,
+            checkpoint,
+            state,
+            resumed: resumed.unwrap_or_default(),
+            dedup: args.dedup.then(Dedup::default),
+            audit: audit.clone(),
+            events: events.clone(),
+            stats: Arc::clone(stats),
+            progress: Arc::new(Progress::new(args.jobs)),
+            scaler: args.auto_jobs.then(|| Arc::new(Scaler::new(args.jobs))),
+            cores,
+        })
+    }
+
+    /// The scope that crawls `dirs` into one set of databases with `hooks`.
+    ///
+    /// In `inspect` mode, it spawns no indexers and creates no databases.
+    fn scope(&self, dirs: Vec<PathBuf>, name: Option<&str>, hooks: Arc<BuildHooks>) -> Result<Scope, Box<dyn Error>> {
+        let Scan { args, inspect, driver, classifier, control, .. } = self;
+        let style = match (&args.relative_to, args.absolute) {
+            (Some(dir), _) => PathStyle::RelativeTo(dir.clone()),
+            (None, true) => PathStyle::Absolute,
+            (None, false) => PathStyle::AsIs,
+        };
+        let mut scope = Scope::builder()
+            .roots(dirs)
+            .excludes(args.excludes.clone().unwrap_or_default())
+            .jobs(args.jobs)
+            .crawl_threads(args.crawl_threads)
+            .schedule(args.schedule)
+            .drivers(DriverList::clone(driver))
+            .classifier(classifier.config().clone())
+            .priority(args.priority.priority())
+            .kernel_mode(args.kernel_mode.unwrap_or(true))
+            .ctags_args(args.ctags_args.clone().unwrap_or_default())
+            .hooks(hooks)
+            .path_style(style)
+            .cancel(control.stop_flag())
+            .drain(control.drain_flag())
+            .keep_partial(args.keep_partial);
+        if let Some(ref languages) = args.languages {
+            scope = scope.languages(languages.clone());
+        }
+        if let Some(limit) = args.queue_limit {
+            scope = scope.queue_limit(limit);
+        }
+        if let Some(since) = args.since {
+            scope = scope.since(since);
+        }
+        if let Some(name) = name {
+            scope = scope.name(name);
+        }
+        if *inspect {
+            scope = scope.backends(&[]);
+        }
+        scope.build()
+    }
+
+    /// Crawl `dirs` and feed all source files into one set of tag databases.
+    ///
+    /// In `inspect` mode, print the decision for each file instead.
+    /// Stop early as requested by `control`.
+    fn run(&self, dirs: Vec<PathBuf>, name: Option<&str>) -> Result<Exit, Box<dyn Error>> {
+        let Scan { args, stats, control, audit, events, metrics, .. } = self;
+        let hooks = Arc::new(self.hooks(name)?);
+        let scope = Arc::new(self.scope(dirs, name, Arc::clone(&hooks))?);
+        if args.preflight {
+            let start = Instant::now();
+            let expected = match hooks.state.as_ref().map(State::len) {
+                Some(n) if n > 0 => n,
+                _ => scope.count()?,
+            };
+            scope.reserve(expected);
+            stats.expected.fetch_add(expected, Ordering::Relaxed);
+            if args.verbose >= 1 {
+                eprintln!("Preflight: {} paths expected ({:.3}s)", expected, start.elapsed().as_secs_f64());
+            }
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let watcher = hooks.progress.watch(
+            Arc::clone(&scope),
+            Arc::clone(stats),
+            args.status_fifo.clone(),
+            events.clone(),
+            metrics.clone(),
+            Arc::clone(&running),
+        )?;
+        let scaling = hooks.scaler.clone().map(|scaler| {
+            let (scope, running) = (Arc::clone(&scope), Arc::clone(&running));
+            thread::spawn(move || scaler.run(&scope, || ! running.load(Ordering::Relaxed)))
+        });
+        let result = scope.run();
+        if let Ok(ref summary) = result {
+            stats.add(summary); // Before the last progress
+        }
+        running.store(false, Ordering::Relaxed);
+        watcher.join().expect("Thread creation or execution failed.");
+        if let Some(scaling) = scaling {
+            scaling.join().expect("Thread creation or execution failed.");
+        }
+        result.map_err(|e| match e.downcast_ref::<ContextError>() {
+            Some(e) if e.context() == "Indexers" => Exit::NoIndexer.error(e),
+            _ => e,
+        })?;
+        if let (Some(scaler), true) = (&hooks.scaler, args.verbose >= 1) {
+            eprintln!("Jobs: {} of {} active at the end, at most {}", scaler.active(), args.jobs, scaler.peak());
+        }
+
+        if control.interrupted() {
+            // The databases are removed, keep the checkpoint for --resume.
+            return Ok(Exit::Interrupted);
+        }
+        if let Some(events) = events {
+            events.phase("done", name);
+        }
+        if let Some(audit) = audit {
+            audit.flush()?;
+        }
+
+        if control.timed_out() {
+            // Keep the checkpoint for --resume.
+            return Ok(Exit::TimedOut);
+        }
+        if control.drained() {
+            // Keep the checkpoint for --resume.
+            return Ok(Exit::Drained);
+        }
+
+        drop(scope); // The last reference besides ours
+        if let Some(hooks) = Arc::into_inner(hooks) {
+            hooks.finish()?;
+        }
+
+        Ok(Exit::Success)
+    }
+}
+
+/// Run `build` or `inspect`.
+///
+/// `per_root` is only available for `build`, `inspect` is the output format,
+/// coloring and the decisions to show for inspection.
+fn build(
+    mut args: ScanArgs,
+    matches: &ArgMatches,
+    inspect: Option<(Format, bool, Option<bool>)>,
+    per_root: Option<bool>,
+) -> Result<Exit, Box<dyn Error>> {
+    let format = inspect;
+    let inspect = format.is_some();
+    let mut per_root = per_root.unwrap_or_default();
+    let dirs = args.dir.clone();
+    let config = configure(&mut args, matches, &dirs)?;
+    if ! inspect && ! is_set(matches, "per_root") {
+        per_root = config.per_root.unwrap_or(per_root);
+    }
+
+    if args.driver.as_deref().is_some_and(|d| d == "list") {
+        return drivers(format.map(|(f, _, _)| f).unwrap_or_default());
+    }
+    check_roots(&args.dir, args.force)?;
+
+    let priority = args.priority.priority();
+    let mut driver = DriverList::new(args.driver.clone(), inspect, priority)
+        .map_err(|e| Exit::Usage.error(e))?;
+    if ! driver.usable() {
+        return Err(Exit::NoDriver.error("No usable driver found."));
+    }
+    driver.set_print0(args.print0);
+    driver.set_sniff_below(match inspect {
+        true => inspect_sniff_below(&args, matches),
+        false => args.sniff_below,
+    });
+    driver.set_helpers(args.driver_helpers.unwrap_or(args.jobs));
+    let mut classifier = FileClassifier::new(classifier_config(&args, &config));
+    if let Some(ref languages) = args.languages {
+        classifier.set_languages(languages)?;
+    }
+    let classifier = Arc::new(classifier);
+
+    if let Some((format, color, show)) = format {
+        driver.set_format(format);
+        driver.set_color(color);
+        driver.set_show(show);
+        if format == Format::Text && ! args.print0 {
+            println!("Driver: {}", driver.name());
+        }
+    }
+    let driver = Arc::new(driver);
+
+    let stats = Arc::new(Stats::default());
+    let audit = match args.log_file {
+        Some(ref path) => Some(Arc::new(AuditLog::create(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?)),
+        None => None,
+    };
+    let events = match args.progress_json {
+        Some(ref path) => Some(Arc::new(Events::create(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?)),
+        None => None,
+    };
+    let metrics = match args.metrics_file {
+        Some(ref path) => Some(Arc::new(Metrics::create(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?)),
+        None => None,
+    };
+    let control = Control::new()?;
+    if let Some(max_runtime) = args.max_runtime {
+        control.set_max_runtime(max_runtime);
+    }
+    if args.verbose >= 1 {
+        eprintln!("Driver: {}, {} index jobs, {} crawl threads",
+            driver.name(), args.jobs, args.crawl_threads);
+    }
+    let scan = Scan {
+        args: &args, inspect, driver, classifier, stats, control, audit, events, metrics,
+    };
+    let mut exit = Exit::Success;
+    if per_root {
+        for (dir, name) in root_names(&args.dir)? {
+            exit = scan.run(vec![dir], Some(&name))?;
+            if exit != Exit::Success {
+                break;
+            }
+        }
+    } else {
+        exit = scan.run(args.dir.clone(), None)?;
+    }
+    let summary = scan.stats.summary(None);
+
+    if ! args.quiet {
+        eprintln!("{}", summary);
+    }
+    if args.stats {
+        print!("{}", scan.stats.languages());
+        print!("{}", summary.worker_report());
+    }
+    if args.timings {
+        print!("{}", summary.timings());
+    }
+    if args.report {
+        print!("{}", summary.report());
+    }
+
+    if exit != Exit::Success {
+        Ok(exit)
+    } else if summary.errors > 0 {
+        Ok(Exit::Partial)
+    } else {
+        Ok(Exit::Success)
+    }
+}
+
+/// The exclude that skips `file` or one of its directories, with the path it matches.
+fn exclude_match(excludes: Vec<String>, file: &Path) -> Option<(String, PathBuf)> {
+    // With negations, the crawler descends into excluded directories.
+    let depth = match excludes.iter().any(|x| x.starts_with('!')) {
+        true => 1,
+        false => usize::MAX,
+    };
+    let crawler = FileCrawler::new(vec![], excludes, Arc::new(FileQueue::new(Schedule::Fifo, 0)));
+    // The crawler sees relative paths below ".".
+    let crawled = match file.is_relative() && ! file.starts_with(".") && ! file.starts_with("..") {
+        true => Path::new(".").join(file),
+        false => file.to_path_buf(),
+    };
+    crawled.ancestors().take(depth)
+        .find_map(|path| Some((crawler.exclude_match(path)?.to_string(), path.to_path_buf())))
+}
+
+/// Run `why`.
+///
+/// Walk through all rules in the order of the scan and print their results.
+fn why(mut args: ScanArgs, matches: &ArgMatches, file: &Path) -> Result<Exit, Box<dyn Error>> {
+    if ! file.exists() {
+        return Err(format!("{}: No such file or directory", file.display()).into());
+    }
+    let root = match file.parent() {
+        Some(p) if ! p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let config = configure(&mut args, matches, &[root])?;
+
+    let mut driver = DriverList::new(args.driver.clone(), false, args.priority.priority())
+        .map_err(|e| Exit::Usage.error(e))?;
+    driver.set_sniff_below(inspect_sniff_below(&args, matches));
+    let mut classifier = FileClassifier::new(classifier_config(&args, &config));
+    if let Some(ref languages) = args.languages {
+        classifier.set_languages(languages)?;
+    }
+    println!("File:      {}", file.display());
+    if let Some((x, path)) = exclude_match(make_excludes(args.excludes.clone()), file) {
+        println!("Exclude:   '{}' matches {}", x, path.display());
+        println!("Decision:  Exclude [pattern]");
+        return Ok(Exit::Success);
+    }
+    println!("Exclude:   no pattern matches");
+
+    let classification = classifier.classify_with(file, || driver.run(file));
+    if let Ok(Classification { decision, .. }) = &classification {
+        if let Some(i) = decision.rule() {
+            println!("Rule:      #{} {} matches", i + 1, classifier.config().rules[i]);
+            println!("Decision:  {}", decision.reason());
+            return Ok(Exit::Success);
+        }
+    }
+    if ! classifier.config().rules.is_empty() {
+        println!("Rule:      no rule matches");
+    }
+
+    let mut by_extension = classifier.language_by_extension(file);
+    match by_extension {
+        Some(language) => println!("Extension: matches {}", language),
+        None => println!("Extension: matches no language"),
+    }
+    if let Some(language) = classifier.language_by_content(file) {
+        let selected = classifier.enabled(language);
+        match selected {
+            true => println!("Content:   looks like {}", language),
+            false => println!("Content:   looks like {}, which is not selected", language),
+        }
+        by_extension = selected.then_some(language);
+    }
+
+    if by_extension.is_none() && classifier.is_binary(file) {
+        println!("Binary:    the extension is never source, no driver asked");
+        println!("Decision:  {}", Reason::ExcludeBinary);
+        return Ok(Exit::Success);
+    }
+
+    let mut selected = None;
+    for d in driver.detect_all(file) {
+        let mark = if d.selected { " (*)" } else { "" };
+        let elapsed = format!("{:.1}ms", d.elapsed.as_secs_f64() * 1000.0);
+        match d.result {
+            None => println!("Driver:    {} (!)", d.driver),
+            Some(Ok(mime)) => {
+                let language = classifier.language_by_mime(&mime);
+                match language {
+                    Some(l) => println!("Driver:    {}{}: {} matches {} ({})",
+                        d.driver, mark, mime, l, elapsed),
+                    None => println!("Driver:    {}{}: {} matches no language ({})",
+                        d.driver, mark, mime, elapsed),
+                }
+                if d.selected {
+                    selected = Some((mime, language));
+                }
+            },
+            Some(Err(e)) => println!("Driver:    {}{}: {} ({})", d.driver, mark, e, elapsed),
+        }
+    }
+
+    let decision = match (by_extension, selected) {
+        (Some(_), _) => Reason::IncludeExtension.to_string(),
+        (None, Some((_, Some(_)))) => Reason::IncludeMime.to_string(),
+        (None, Some((_, None))) => Reason::ExcludeMime.to_string(),
+        (None, None) => "Exclude (no MIME type)".to_string(),
+    };
+    println!("Decision:  {}", decision);
+
+    Ok(Exit::Success)
+}
+
+/// Run `man`.
+fn man(args: ManArgs) -> Result<(), Box<dyn Error>> {
+    let cmd = Args::command().after_help(EXIT_STATUS);
+    let Some(dir) = args.dir else {
+        Man::new(cmd).render(&mut io::stdout())?;
+        return Ok(());
+    };
+
+    fs::create_dir_all(&dir)?;
+    let mut file = fs::File::create(dir.join("scope.1"))?;
+    Man::new(cmd.clone()).render(&mut file)?;
+    for sub in cmd.get_subcommands() {
+        let name = format!("scope-{}", sub.get_name());
+        let mut file = fs::File::create(dir.join(format!("{}.1", name)))?;
+        Man::new(sub.clone()).title(name).render(&mut file)?;
+    }
+    Ok(())
+}
+
+/// Decide whether to colorize output for `--color <when>`.
+fn use_color(when: &str) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        _ => io::stdout().is_terminal()
+            && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    }
+}
+
+/// Run `tui`.
+#[cfg(feature = "tui")]
+fn tui(mut args: ScanArgs, matches: &ArgMatches) -> Result<Exit, Box<dyn Error>> {
+    let dirs = args.dir.clone();
+    let config = configure(&mut args, matches, &dirs)?;
+    check_roots(&args.dir, args.force)?;
+
+    let mut driver = DriverList::new(args.driver.clone(), true, args.priority.priority())
+        .map_err(|e| Exit::Usage.error(e))?;
+    driver.set_sniff_below(inspect_sniff_below(&args, matches));
+    driver.set_helpers(args.driver_helpers.unwrap_or(args.jobs));
+    if ! driver.usable() {
+        return Err(Exit::NoDriver.error("No usable driver found."));
+    }
+    let classifier = FileClassifier::new(classifier_config(&args, &config));
+    let enabled: Vec<String> = match args.languages {
+        Some(ref languages) => {
+            // Only validate, the TUI considers all languages.
+            classifier.clone().set_languages(languages)?;
+            languages.iter().map(|l| l.to_lowercase()).collect()
+        },
+        None => classifier.config().languages().map(String::from).collect(),
+    };
+
+    let excludes = make_excludes(args.excludes.clone());
+    tui::run(driver, classifier, args.dir.clone(), excludes, args.jobs, &enabled)?;
+    Ok(Exit::Success)
+}
+
+/// Run `drivers`.
+fn drivers(format: Format) -> Result<Exit, Box<dyn Error>> {
+    let driver = DriverList::new(None, false, Priority::default())?;
+    match format {
+        Format::Text => println!("{}", driver),
+        Format::Json => println!("{}", driver.json()),
+    }
+    Ok(Exit::Success)
+}
+
+/// Run `update`.
+fn update(mut args: UpdateArgs, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let dirs = args.scan.dir.clone();
+    let config = configure(&mut args.scan, matches, &dirs)?;
+    let scan = &args.scan;
+    let priority = scan.priority.priority();
+    let mut driver = DriverList::new(scan.driver.clone(), false, priority)
+        .map_err(|e| Exit::Usage.error(e))?;
+    if ! driver.usable() {
+        return Err(Exit::NoDriver.error("No usable driver found."));
+    }
+    driver.set_sniff_below(scan.sniff_below);
+    let mut classifier = FileClassifier::new(classifier_config(scan, &config));
+    if let Some(ref languages) = scan.languages {
+        classifier.set_languages(languages)?;
+    }
+
+    // Decide like `build`, so that the file is in the tags if and only if a build adds it.
+    let file = &args.file;
+    let include = exclude_match(make_excludes(scan.excludes.clone()), file).is_none()
+        && classifier.classify(file, &driver).is_ok_and(|c| c.decision.include());
+    TagFileCreator::update(priority, &args.tag_file, file, include)
+}
+
+/// Run `languages`.
+///
+/// List the languages of the effective configuration, those selected with
+/// `--languages` only, with their extensions and MIME types.
+fn languages(mut args: ScanArgs, matches: &ArgMatches) -> Result<Exit, Box<dyn Error>> {
+    let dirs = args.dir.clone();
+    let config = configure(&mut args, matches, &dirs)?;
+    let mut classifier = FileClassifier::new(classifier_config(&args, &config));
+    if let Some(ref languages) = args.languages {
+        classifier.set_languages(languages)?;
+    }
+    let config = classifier.config();
+    let of = |table: &[(String, Vec<String>)], language: &str| -> Vec<String> {
+        table.iter().filter(|(l, _)| l == language).flat_map(|(_, v)| v.iter().cloned()).collect()
+    };
+    for language in config.languages().filter(|l| classifier.enabled(l)) {
+        let extensions: Vec<String> = of(&config.extensions, language).iter()
+            .map(|e| format!(".{}", e))
+            .collect();
+        let mimetypes = of(&config.mimetypes, language);
+        if extensions.is_empty() && mimetypes.is_empty() {
+            continue; // Cleared by the configuration
+        }
+        let line = format!("{:12} {:30} {}", language, extensions.join(" "), mimetypes.join(" "));
+        println!("{}", line.trim_end());
+    }
+    Ok(Exit::Success)
+}
+
+/// Run `clean`.
+fn clean(args: CleanArgs) -> Result<(), Box<dyn Error>> {
+    let mut databases = TagFileCreator::databases(None);
+    if args.per_root {
+        for (_, name) in root_names(&args.dir)? {
+            databases.extend(TagFileCreator::databases(Some(&name)));
+        }
+    }
+    for db in databases {
+        match fs::remove_file(&db) {
+            Ok(()) => println!("Removed {}", db.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(format!("{}: {}", db.display(), e).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Run the subcommand from the command line.
+fn run(matches: ArgMatches) -> Result<Exit, Box<dyn Error>> {
+    let args = Args::from_arg_matches(&matches)
+        .map_err(|e| Exit::Usage.error(e))?;
+
+    //println!("{:#?}", args);
+    match args.command {
+        None => {
+            let _lock = args.build.lock.acquire()?;
+            build(args.build.scan, &matches, None, Some(args.build.per_root))
+        },
+        Some(Commands::Build(b)) => {
+            let matches = matches.subcommand_matches("build").unwrap_or(&matches);
+            let _lock = b.lock.acquire()?;
+            build(b.scan, matches, None, Some(b.per_root))
+        },
+        Some(Commands::Inspect(i)) => {
+            let matches = matches.subcommand_matches("inspect").unwrap_or(&matches);
+            let inspect = (i.format, use_color(&i.color), i.show());
+            build(i.scan, matches, Some(inspect), None)
+        },
+        Some(Commands::Why(w)) => {
+            let matches = matches.subcommand_matches("why").unwrap_or(&matches);
+            why(w.scan, matches, &w.file)
+        },
+        #[cfg(feature = "tui")]
+        Some(Commands::Tui(t)) => {
+            let matches = matches.subcommand_matches("tui").unwrap_or(&matches);
+            tui(t.scan, matches)
+        },
+        Some(Commands::Drivers(d)) => drivers(d.format),
+        Some(Commands::Languages(l)) => {
+            let matches = matches.subcommand_matches("languages").unwrap_or(&matches);
+            languages(l.scan, matches)
+        },
+        Some(Commands::Update(u)) => {
+            let matches = matches.subcommand_matches("update").unwrap_or(&matches);
+            let _lock = u.lock.acquire()?;
+            update(u, matches).map(|_| Exit::Success)
+        },
+        Some(Commands::Clean(c)) => {
+            let _lock = c.lock.acquire()?;
+            clean(c).map(|_| Exit::Success)
+        },
+        Some(Commands::Config(c)) => {
+            let matches = matches.subcommand_matches("config")
+                .and_then(|m| m.subcommand_matches("show"))
+                .unwrap_or(&matches);
+            match c.command {
+                ConfigCommands::Show(b) => config_show(b, matches).map(|_| Exit::Success),
+            }
+        },
+        Some(Commands::Man(m)) => man(m).map(|_| Exit::Success),
+    }
+}
+
+fn main() -> ExitCode {
+    let matches = Args::command()
+        .after_help(EXIT_STATUS)
+        .get_matches();
+
+    match run(matches) {
+        Ok(exit) => exit.into(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Exit::of(e.as_ref()).into()
+        },
+    }
+}
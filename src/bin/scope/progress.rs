@@ -0,0 +1,267 @@
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use signal_hook::consts::SIGUSR1;
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
+
+use scope_rs::{ScanSummary, Scope};
+
+use crate::stats::Stats;
+
+/// How often the watcher looks for requests.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often progress events are emitted.
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Stream of progress events, one JSON object per line.
+///
+/// Each event has the `event` type and the `elapsed` seconds since the start.
+/// `phase` events name the `phase` that begins: crawl, finalize or done.
+/// `progress` events carry the counts so far and the throughput.
+pub struct Events {
+    file: Mutex<File>,
+    start: Instant,
+}
+
+impl Events {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        Ok(Events { file: Mutex::new(file), start: Instant::now() })
+    }
+
+    /// A new `phase` begins, for the databases with an optional `name`.
+    pub fn phase(&self, phase: &str, name: Option<&str>) {
+        self.emit(serde_json::json!({
+            "event": "phase",
+            "phase": phase,
+            "name": name,
+        }));
+    }
+
+    /// Report the current counts of `summary`, with the `expected` paths if known.
+    pub fn progress(&self, queued: usize, summary: &ScanSummary, expected: usize) {
+        let processed = summary.crawled;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        self.emit(serde_json::json!({
+            "event": "progress",
+            "queued": queued,
+            "processed": processed,
+            "expected": Some(expected).filter(|n| *n > 0),
+            "included": summary.included(),
+            "excluded": summary.excluded(),
+            "errors": summary.errors,
+            "files_per_second": processed as f64 / elapsed.max(f64::EPSILON),
+        }));
+    }
+
+    fn emit(&self, mut event: serde_json::Value) {
+        event["elapsed"] = self.start.elapsed().as_secs_f64().into();
+        // Progress is best effort, a reader that went away must not stop the run.
+        writeln!(self.file.lock().unwrap(), "{}", event).unwrap_or_default();
+    }
+}
+
+/// Metrics in the Prometheus text format, for the textfile collector
+/// of node_exporter.
+///
+/// The file is replaced as a whole, so that a scrape never sees half of it.
+pub struct Metrics {
+    path: PathBuf,
+}
+
+impl Metrics {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let metrics = Metrics { path: path.to_path_buf() };
+        metrics.write(0, &ScanSummary::default(), 0)?;
+        Ok(metrics)
+    }
+
+    /// Replace the file with the current counts.
+    pub fn write(&self, queued: usize, summary: &ScanSummary, expected: usize) -> io::Result<()> {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            write!(text, "# HELP {0} {1}\n# TYPE {0} {2}\n{0} {3}\n", name, help, kind, value).unwrap();
+        };
+        metric("scope_files_crawled_total", "counter", "Files crawled.",
+            summary.crawled as f64);
+        metric("scope_files_expected", "gauge", "Files the crawler is expected to find, 0 if unknown.",
+            expected as f64);
+        metric("scope_files_indexed_total", "counter", "Files passed to the indexers.",
+            summary.included() as f64);
+        metric("scope_files_excluded_total", "counter", "Files excluded.",
+            summary.excluded() as f64);
+        metric("scope_errors_total", "counter", "Files that could not be classified or indexed.",
+            summary.errors as f64);
+        metric("scope_backend_errors_total", "counter", "Files the indexers did not take.",
+            summary.backend_errors as f64);
+        metric("scope_queue_depth", "gauge", "Files crawled but not classified yet.",
+            queued as f64);
+        let driver_time = summary.driver_time.as_secs_f64();
+        write!(text, "# HELP scope_driver_seconds Time the MIME driver took per file.\n\
+            # TYPE scope_driver_seconds summary\n\
+            scope_driver_seconds_sum {}\nscope_driver_seconds_count {}\n",
+            driver_time, summary.driver_calls).unwrap();
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, text)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+/// What each worker thread is doing right now.
+pub struct Progress {
+    workers: Vec<Mutex<Option<PathBuf>>>,
+}
+
+impl Progress {
+    pub fn new(jobs: usize) -> Self {
+        Progress { workers: (0..jobs).map(|_| Mutex::new(None)).collect() }
+    }
+
+    /// Worker `id` works on `path` now, or waits for work with `None`.
+    pub fn set(&self, id: usize, path: Option<&Path>) {
+        *self.workers[id].lock().unwrap() = path.map(Path::to_path_buf);
+    }
+
+    /// Describe the progress of the run.
+    pub fn report(&self, queued: usize, summary: &ScanSummary, expected: usize) -> String {
+        let mut result = String::new();
+        writeln!(result, "Queued:     {:>10}", queued).unwrap();
+        let processed = summary.crawled;
+        match expected {
+            0 => writeln!(result, "Processed:  {:>10}", processed),
+            expected => writeln!(result, "Processed:  {:>10} of {} ({:.0}%)", processed, expected,
+                (processed as f64 / expected as f64 * 100.0).min(100.0)),
+        }.unwrap();
+        for (id, worker) in self.workers.iter().enumerate() {
+            match &*worker.lock().unwrap() {
+                Some(path) => writeln!(result, "Worker {:<3}  {}", id, path.display()),
+                None => writeln!(result, "Worker {:<3}  idle", id),
+            }.unwrap();
+        }
+        result
+    }
+
+    /// Print a report on SIGUSR1 to stderr, and to each reader of `fifo`,
+    /// and emit progress `events` and `metrics` of the run of `scope` until
+    /// `watching` is cleared.
+    ///
+    /// The FIFO is created if it does not exist and removed afterwards.
+    /// Without Unix, there are neither SIGUSR1 nor FIFOs.
+    pub fn watch(
+        self: &Arc<Self>,
+        scope: Arc<Scope>,
+        stats: Arc<Stats>,
+        fifo: Option<PathBuf>,
+        events: Option<Arc<Events>>,
+        metrics: Option<Arc<Metrics>>,
+        watching: Arc<AtomicBool>,
+    ) -> io::Result<JoinHandle<()>> {
+        #[cfg(unix)]
+        let mut signals = Signals::new([SIGUSR1])?;
+        let created = match fifo {
+            Some(ref fifo) => mkfifo(fifo)?,
+            None => false,
+        };
+        let progress = Arc::clone(self);
+        // The counts of the databases done and of the current run.
+        let current = move || (scope.queued(), stats.summary(scope.summary().as_ref()),
+            stats.expected.load(Ordering::Relaxed));
+        let report = {
+            let current = current.clone();
+            move || {
+                let (queued, summary, expected) = current();
+                progress.report(queued, &summary, expected)
+            }
+        };
+
+        Ok(thread::spawn(move || {
+            let mut emitted = Instant::now();
+            let emit = || {
+                let (queued, summary, expected) = current();
+                if let Some(ref events) = events {
+                    events.progress(queued, &summary, expected);
+                }
+                if let Some(ref metrics) = metrics {
+                    // Metrics are best effort, like progress.
+                    metrics.write(queued, &summary, expected).unwrap_or_default();
+                }
+            };
+            while watching.load(Ordering::Relaxed) {
+                if emitted.elapsed() >= EVENT_INTERVAL {
+                    emit();
+                    emitted = Instant::now();
+                }
+                #[cfg(unix)]
+                if signals.pending().count() > 0 {
+                    eprint!("{}", report());
+                }
+                if let Some(mut reader) = fifo.as_deref().and_then(open_reader) {
+                    reader.write_all(report().as_bytes()).unwrap_or_default();
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            emit();
+            #[cfg(unix)]
+            signals.handle().close();
+            if let (Some(fifo), true) = (fifo, created) {
+                fs::remove_file(fifo).unwrap_or_default();
+            }
+        }))
+    }
+}
+
+/// The FIFO at `path`, if a reader waits on it.
+#[cfg(unix)]
+fn open_reader(path: &Path) -> Option<File> {
+    // Opening fails without a reader, instead of blocking.
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn open_reader(_path: &Path) -> Option<File> {
+    None
+}
+
+/// Create a FIFO at `path`, unless there is one already.
+///
+/// Returns whether it was created.
+#[cfg(unix)]
+fn mkfifo(path: &Path) -> io::Result<bool> {
+    match fs::metadata(path) {
+        Ok(m) if m.file_type().is_fifo() => return Ok(false),
+        Ok(_) => return Err(io::Error::other(
+            format!("{}: Not a FIFO", path.display()))),
+        Err(_) => (),
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn mkfifo(path: &Path) -> io::Result<bool> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("{}: FIFOs need Unix", path.display())))
+}
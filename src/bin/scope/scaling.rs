@@ -0,0 +1,157 @@
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use scope_rs::Scope;
+
+/// How often the number of active jobs is adjusted.
+const INTERVAL: Duration = Duration::from_millis(200);
+
+/// Share of their time the active jobs may wait for the indexers before one is parked.
+const BLOCKED_SHARE: f64 = 0.2;
+
+/// Adjustments a job that did not pay off is not tried again for.
+const BACKOFF: u32 = 10;
+
+/// What the jobs did during one interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    active: usize,
+    /// Files taken from the queue.
+    files: usize,
+    driver_calls: usize,
+    driver_time: Duration,
+}
+
+impl Sample {
+    /// Average time of a driver call.
+    fn latency(&self) -> Duration {
+        match self.driver_calls {
+            0 => Duration::ZERO,
+            n => self.driver_time / n as u32,
+        }
+    }
+}
+
+/// Parks and wakes jobs for `--jobs auto`, as the bottleneck moves.
+///
+/// Jobs are added while files wait in the queue and the indexers keep up.
+/// Jobs are parked while they wait for the indexers to take their files,
+/// and when another job made the drivers slower without classifying more.
+pub struct Scaler {
+    max: usize,
+    active: Mutex<usize>,
+    changed: Condvar,
+    /// Nanoseconds the jobs waited for the writer, since the last adjustment.
+    blocked: AtomicU64,
+    /// Jobs waiting for the writer right now.
+    sending: AtomicUsize,
+    peak: Mutex<usize>,
+}
+
+impl Scaler {
+    /// Up to `max` jobs, half of them active at first.
+    pub fn new(max: usize) -> Self {
+        let active = max.div_ceil(2);
+        Scaler { max, active: Mutex::new(active), changed: Condvar::new(), blocked: AtomicU64::new(0),
+            sending: AtomicUsize::new(0), peak: Mutex::new(active) }
+    }
+
+    /// Number of active jobs.
+    pub fn active(&self) -> usize {
+        *self.active.lock().unwrap()
+    }
+
+    /// Most jobs that were active at once.
+    pub fn peak(&self) -> usize {
+        *self.peak.lock().unwrap()
+    }
+
+    /// Park job `id` while it is not active.
+    ///
+    /// Returns `false` once `done` tells that the job is not needed anymore.
+    pub fn admit(&self, id: usize, done: impl Fn() -> bool) -> bool {
+        let mut active = self.active.lock().unwrap();
+        while id >= *active {
+            if done() {
+                return false;
+            }
+            active = self.changed.wait_timeout(active, INTERVAL).unwrap().0;
+        }
+        true
+    }
+
+    /// Hand a file to the writer with `send`, accounting the time it blocks.
+    pub fn send<R>(&self, send: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        self.sending.fetch_add(1, Ordering::Relaxed);
+        let result = send();
+        self.sending.fetch_sub(1, Ordering::Relaxed);
+        self.blocked.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    /// Adjust the active jobs every interval from the counts and the
+    /// queued files of the run of `scope`, until `done`.
+    pub fn run(&self, scope: &Scope, done: impl Fn() -> bool) {
+        let totals = || {
+            let summary = scope.summary().unwrap_or_default();
+            Sample {
+                active: self.active(),
+                files: summary.crawled,
+                driver_calls: summary.driver_calls,
+                driver_time: summary.driver_time,
+            }
+        };
+        let (mut before, mut last) = (totals(), None);
+        let mut backoff: u32 = 0;
+        let mut started = Instant::now();
+        while ! done() {
+            std::thread::sleep(INTERVAL);
+            let now = totals();
+            let sample = Sample {
+                active: before.active,
+                files: now.files.saturating_sub(before.files),
+                driver_calls: now.driver_calls.saturating_sub(before.driver_calls),
+                driver_time: now.driver_time.saturating_sub(before.driver_time),
+            };
+            // Jobs blocked for the whole interval only count once they are done,
+            // those waiting right now count as well.
+            let blocked = Duration::from_nanos(self.blocked.swap(0, Ordering::Relaxed));
+            let share = f64::max(
+                blocked.as_secs_f64() / (sample.active as f64 * started.elapsed().as_secs_f64()),
+                self.sending.load(Ordering::Relaxed) as f64 / sample.active as f64);
+            started = Instant::now();
+            backoff = backoff.saturating_sub(1);
+
+            let active = if share > BLOCKED_SHARE {
+                // The indexers are the bottleneck.
+                backoff = BACKOFF;
+                sample.active - 1
+            } else if scope.queued() <= sample.active {
+                // The crawler is the bottleneck, more jobs would only wait.
+                sample.active
+            } else if last.is_some_and(|last: Sample| last.active < sample.active
+                && sample.files as f64 <= last.files as f64 * 1.05
+                && sample.latency().as_secs_f64() > last.latency().as_secs_f64() * 1.25) {
+                // The last job made the drivers slower, not the build faster.
+                backoff = BACKOFF;
+                sample.active - 1
+            } else if backoff == 0 {
+                sample.active + 1
+            } else {
+                sample.active
+            };
+            self.set(active);
+            (before, last) = (Sample { active: self.active(), ..now }, Some(sample));
+        }
+    }
+
+    fn set(&self, active: usize) {
+        let active = active.clamp(1, self.max);
+        *self.active.lock().unwrap() = active;
+        let mut peak = self.peak.lock().unwrap();
+        *peak = (*peak).max(active);
+        self.changed.notify_all();
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::checkpoint::path_from_bytes;
+
+/// What the last run knew about a file.
+struct Entry {
+    size: u64,
+    /// Modification time in nanoseconds since the epoch.
+    mtime: u128,
+    /// BLAKE3 hash of an included file.
+    hash: Option<String>,
+    language: Option<String>,
+}
+
+/// Size, modification time and content of the files of the last complete
+/// build, so that the drivers only classify files that changed.
+///
+/// The first record is the fingerprint of the settings that decided, see
+/// `Scan::fingerprint()`. The state of a build with other settings is
+/// ignored. Each other record is the size, the modification time in
+/// nanoseconds, the hash of an included file or `-`, its language or `-` and
+/// the path, separated by tabs and terminated by NUL. The next state is written next to it and
+/// only replaces it after a complete build.
+pub struct State {
+    path: PathBuf,
+    next: PathBuf,
+    previous: HashMap<PathBuf, Entry>,
+    /// The last build had other settings.
+    outdated: bool,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl State {
+    /// Name of the state for the databases with an optional `name`.
+    pub fn path(name: Option<&str>) -> PathBuf {
+        match name {
+            Some(name) => PathBuf::from(format!(".scope.{}.state", name)),
+            None => PathBuf::from(".scope.state"),
+        }
+    }
+
+    /// Read the state of the last build at `path`, if any and with the same
+    /// settings `fingerprint`, and start the next one.
+    pub fn open(path: &Path, fingerprint: &str) -> io::Result<Self> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut previous = HashMap::new();
+        let mut records = content.split(|b| *b == 0);
+        let outdated = ! content.is_empty() && records.next() != Some(fingerprint.as_bytes());
+        for record in records.take_while(|_| ! outdated) {
+            let mut fields = record.splitn(5, |b| *b == b'\t');
+            let (Some(size), Some(mtime), Some(hash), Some(language), Some(file)) =
+                (fields.next(), fields.next(), fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            // Only the path may be any bytes.
+            let text = |field| std::str::from_utf8(field).ok();
+            let (Some(size), Some(mtime), Some(hash), Some(language), Some(file)) = (
+                text(size).and_then(|s| s.parse().ok()),
+                text(mtime).and_then(|m| m.parse().ok()),
+                text(hash),
+                text(language),
+                path_from_bytes(file),
+            ) else {
+                continue;
+            };
+            let entry = Entry {
+                size,
+                mtime,
+                hash: (hash != "-").then(|| hash.to_string()),
+                language: (language != "-").then(|| language.to_string()),
+            };
+            previous.insert(file, entry);
+        }
+        let mut next = path.as_os_str().to_owned();
+        next.push(".next");
+        let next = PathBuf::from(next);
+        let mut file = BufWriter::new(File::create(&next)?);
+        file.write_all(fingerprint.as_bytes())?;
+        file.write_all(b"\0")?;
+        Ok(State {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            next,
+            previous,
+            outdated,
+        })
+    }
+
+    /// The last build had other settings, its state is not used.
+    pub fn outdated(&self) -> bool {
+        self.outdated
+    }
+
+    /// Number of files of the last build.
+    pub fn len(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Size and modification time of `file`.
+    fn stat(file: &Path) -> io::Result<(u64, u128)> {
+        let metadata = fs::metadata(file)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok((metadata.len(), mtime.as_nanos()))
+    }
+
+    fn hash(file: &Path) -> io::Result<String> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(File::open(file)?)?;
+        Ok(hasher.finalize().to_hex().to_string())
+    }
+
+    /// The decision of the last build for `file`, if it did not change since.
+    ///
+    /// Included files map to their language, excluded ones to `None`.
+    /// A file with a new modification time but the same size is unchanged
+    /// if its content has the same hash, excluded files are not hashed.
+    pub fn unchanged(&self, file: &Path) -> Option<Option<&str>> {
+        let entry = self.previous.get(file)?;
+        let (size, mtime) = State::stat(file).ok()?;
+        if size != entry.size {
+            return None;
+        }
+        let same = mtime == entry.mtime
+            || entry.hash.as_ref().is_some_and(|hash| State::hash(file).is_ok_and(|h| h == *hash));
+        same.then_some(entry.language.as_deref())
+    }
+
+    /// Record the decision for `file`, with the `language` of included files.
+    ///
+    /// Included files are hashed, unless they kept size and modification time.
+    pub fn record(&self, file: &Path, language: Option<&str>) -> io::Result<()> {
+        let (size, mtime) = State::stat(file)?;
+        let hash = match (language, self.previous.get(file)) {
+            (None, _) => None,
+            (Some(_), Some(Entry { size: s, mtime: m, hash: Some(hash), .. })) if (*s, *m) == (size, mtime) =>
+                Some(hash.clone()),
+            (Some(_), _) => Some(State::hash(file)?),
+        };
+        let mut writer = self.file.lock().unwrap();
+        write!(writer, "{}\t{}\t{}\t{}\t", size, mtime, hash.as_deref().unwrap_or("-"), language.unwrap_or("-"))?;
+        writer.write_all(file.as_os_str().as_encoded_bytes())?;
+        writer.write_all(b"\0")
+    }
+
+    /// Replace the state of the last build after a complete one.
+    pub fn finish(self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()?;
+        fs::rename(&self.next, &self.path)
+    }
+}
+
+impl Drop for State {
+    /// Drop the next state of an incomplete build.
+    fn drop(&mut self) {
+        fs::remove_file(&self.next).unwrap_or_default();
+    }
+}
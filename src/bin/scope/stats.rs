@@ -0,0 +1,79 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+
+use scope_rs::ScanSummary;
+
+/// Bytes read at once to count lines.
+const COUNT_SIZE: usize = 64 * 1024;
+
+thread_local! {
+    /// The buffer to count lines in, kept for the next file.
+    static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Statistics collected during a run, over all sets of databases.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Paths the crawler is expected to find with `--preflight`, 0 if unknown.
+    pub expected: AtomicUsize,
+    languages: Mutex<BTreeMap<String, (usize, usize)>>,
+    /// The counts of the sets of databases done so far.
+    done: Mutex<ScanSummary>,
+}
+
+impl Stats {
+    /// Count the file at `path` and its lines for `language`.
+    pub fn language(&self, language: &str, path: &Path) {
+        let lines = BUFFER.with_borrow_mut(|buffer| {
+            buffer.resize(COUNT_SIZE, 0);
+            let mut file = File::open(path)?;
+            let mut lines = 0;
+            loop {
+                match file.read(buffer)? {
+                    0 => return Ok::<_, std::io::Error>(lines),
+                    n => lines += buffer[..n].iter().filter(|b| **b == b'\n').count(),
+                }
+            }
+        }).unwrap_or_default();
+        let mut languages = self.languages.lock().unwrap();
+        if ! languages.contains_key(language) {
+            languages.insert(language.to_string(), (0, 0));
+        }
+        let entry = languages.get_mut(language).unwrap();
+        entry.0 += 1;
+        entry.1 += lines;
+    }
+
+    /// Table of files and lines per language.
+    pub fn languages(&self) -> String {
+        let languages = self.languages.lock().unwrap();
+        let mut result = format!("{:12} {:>10} {:>12}\n", "Language", "Files", "Lines");
+        let (mut files, mut lines) = (0, 0);
+        for (language, (f, l)) in languages.iter() {
+            result.push_str(&format!("{:12} {:>10} {:>12}\n", language, f, l));
+            files += f;
+            lines += l;
+        }
+        result.push_str(&format!("{:12} {:>10} {:>12}\n", "Total", files, lines));
+        result
+    }
+
+    /// Add the counts of one set of databases.
+    pub fn add(&self, summary: &ScanSummary) {
+        self.done.lock().unwrap().add(summary);
+    }
+
+    /// The counts so far, with those of the `current` run.
+    pub fn summary(&self, current: Option<&ScanSummary>) -> ScanSummary {
+        let mut summary = self.done.lock().unwrap().clone();
+        if let Some(current) = current {
+            summary.add(current);
+        }
+        summary
+    }
+}
@@ -0,0 +1,302 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Row, Table, TableState};
+
+use scope_rs::{DriverList, FileClassifier, FileCrawler, FileQueue, MimeType, Reason, Schedule};
+
+use crate::config::CONFIG_FILE;
+
+/// How often the screen is refreshed while files come in.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Classification of one file, independent of the selected languages.
+struct Entry {
+    path: PathBuf,
+    language: Option<String>,
+    reason: Reason,
+    mime: Option<MimeType>,
+}
+
+impl Entry {
+    /// The decision for this file with only the `enabled` ones of the `languages`.
+    ///
+    /// This is a preview: a file whose extension belongs to a disabled
+    /// language would be checked by the driver in a real run.
+    fn reason(&self, languages: &[String], enabled: &[bool]) -> Reason {
+        let enabled = self.language.as_ref()
+            .and_then(|l| languages.iter().position(|m| m == l))
+            .is_some_and(|i| enabled[i]);
+        match (enabled, self.reason) {
+            (_, reason @ (Reason::ExcludeRule | Reason::ExcludeBinary)) => reason,
+            (true, reason) => reason,
+            (false, _) => Reason::ExcludeMime,
+        }
+    }
+}
+
+/// Which decisions to show.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Show {
+    All,
+    Included,
+    Excluded,
+}
+
+/// State of the interactive inspection.
+struct App {
+    entries: Vec<Entry>,
+    names: Vec<String>,
+    enabled: Vec<bool>,
+    show: Show,
+    filter: String,
+    editing: bool,
+    languages_focused: bool,
+    files: TableState,
+    languages: ListState,
+    message: String,
+}
+
+impl App {
+    fn visible(&self) -> impl Iterator<Item = (&Entry, Reason)> {
+        self.entries.iter()
+            .map(|e| (e, e.reason(&self.names, &self.enabled)))
+            .filter(|(_, r)| match self.show {
+                Show::All => true,
+                Show::Included => r.include(),
+                Show::Excluded => ! r.include(),
+            })
+            .filter(|(e, _)| self.filter.is_empty()
+                || e.path.to_string_lossy().contains(&self.filter)
+                || e.mime.as_ref().is_some_and(|m| m.to_string().contains(&self.filter)))
+    }
+
+    fn draw(&mut self, frame: &mut ratatui::Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(2)])
+            .areas(frame.area());
+        let [files, languages] = Layout::horizontal([Constraint::Min(1), Constraint::Length(20)])
+            .areas(main);
+
+        let rows: Vec<Row> = self.visible().map(|(e, reason)| {
+            let color = if reason.include() { Color::Green } else { Color::Red };
+            Row::new([
+                reason.to_string(),
+                e.mime.as_ref().map(MimeType::to_string).unwrap_or_default(),
+                e.path.display().to_string(),
+            ]).style(Style::default().fg(color))
+        }).collect();
+        let count = rows.len();
+        let table = Table::new(rows, [Constraint::Length(14), Constraint::Length(29), Constraint::Min(1)])
+            .block(Block::bordered().title(format!(" Files ({}) ", count)))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, files, &mut self.files);
+
+        let items: Vec<ListItem> = self.names.iter().zip(&self.enabled)
+            .map(|(l, on)| ListItem::new(format!("[{}] {}", if *on { 'x' } else { ' ' }, l)))
+            .collect();
+        let highlight = match self.languages_focused {
+            true => Style::default().add_modifier(Modifier::REVERSED),
+            false => Style::default(),
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(" Languages "))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, languages, &mut self.languages);
+
+        let show = match self.show {
+            Show::All => "all",
+            Show::Included => "included",
+            Show::Excluded => "excluded",
+        };
+        let filter = match self.editing {
+            true => format!("/{}_", self.filter),
+            false => self.filter.clone(),
+        };
+        let help = Line::from(format!(
+            "Show: {}  Filter: {}  {}", show, filter, self.message));
+        let keys = Line::from(
+            "q quit  f included/excluded  / filter  tab languages  space toggle  w write config");
+        frame.render_widget(Paragraph::new(vec![help, keys]), status);
+    }
+
+    /// Handle a key press, return false to quit.
+    fn key(&mut self, code: KeyCode) -> bool {
+        if self.editing {
+            match code {
+                KeyCode::Enter | KeyCode::Esc => self.editing = false,
+                KeyCode::Backspace => { self.filter.pop(); },
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => (),
+            }
+            return true;
+        }
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('f') => self.show = match self.show {
+                Show::All => Show::Included,
+                Show::Included => Show::Excluded,
+                Show::Excluded => Show::All,
+            },
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Tab => self.languages_focused = ! self.languages_focused,
+            KeyCode::Char(' ') if self.languages_focused => {
+                if let Some(i) = self.languages.selected() {
+                    self.enabled[i] = ! self.enabled[i];
+                }
+            },
+            KeyCode::Char('w') => self.message = match self.write_config() {
+                Ok(()) => format!("Wrote languages to {}.", CONFIG_FILE),
+                Err(e) => format!("Cannot write {}: {}", CONFIG_FILE, e),
+            },
+            KeyCode::Down | KeyCode::Char('j') => match self.languages_focused {
+                true => self.languages.select_next(),
+                false => self.files.select_next(),
+            },
+            KeyCode::Up | KeyCode::Char('k') => match self.languages_focused {
+                true => self.languages.select_previous(),
+                false => self.files.select_previous(),
+            },
+            KeyCode::PageDown => self.files.scroll_down_by(20),
+            KeyCode::PageUp => self.files.scroll_up_by(20),
+            _ => (),
+        }
+        true
+    }
+
+    /// Set the enabled languages in the configuration file of the current directory.
+    fn write_config(&self) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(CONFIG_FILE);
+        let mut config: toml::Table = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(_) => toml::Table::new(),
+        };
+        let languages: Vec<toml::Value> = self.names.iter().zip(&self.enabled)
+            .filter(|(_, on)| **on)
+            .map(|(l, _)| l.as_str().into())
+            .collect();
+        config.insert("languages".into(), languages.into());
+        fs::write(path, toml::to_string(&config)?)?;
+        Ok(())
+    }
+}
+
+/// Classify all files below `dirs` in the background.
+///
+/// All languages are considered, so that the preview can toggle them.
+fn classify(
+    driver: Arc<DriverList>,
+    classifier: Arc<FileClassifier>,
+    dirs: Vec<PathBuf>,
+    excludes: Vec<String>,
+    jobs: usize,
+    stop: Arc<AtomicBool>,
+) -> Receiver<Entry> {
+    let (sender, receiver) = mpsc::channel();
+    let files_to_scan = Arc::new(FileQueue::new(Schedule::Fifo, jobs));
+    let running = Arc::new(AtomicBool::new(true));
+
+    for _ in 0..jobs {
+        let queue = files_to_scan.worker(); // Consumer
+        let driver = Arc::clone(&driver);
+        let classifier = Arc::clone(&classifier);
+        let running = Arc::clone(&running);
+        let stop = Arc::clone(&stop);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            while ! stop.load(Ordering::Relaxed) {
+                let Some(path) = queue.pop() else {
+                    if ! running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                };
+                let entry = match classifier.classify(&path, driver.as_ref()) {
+                    Ok(c) => Entry {
+                        path,
+                        language: c.language,
+                        reason: c.decision.reason(),
+                        mime: c.decision.mime().cloned(),
+                    },
+                    Err(_) => Entry { path, language: None, reason: Reason::ExcludeMime, mime: None },
+                };
+                if sender.send(entry).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        let mut crawler = FileCrawler::new(dirs, excludes, files_to_scan); // Producer
+        crawler.set_cancel(stop);
+        crawler.run().unwrap_or_default();
+        running.store(false, Ordering::Relaxed);
+    });
+    receiver
+}
+
+/// Show the decisions for all files below `dirs` interactively.
+///
+/// `enabled` are the names of the languages to start with.
+pub fn run(
+    driver: DriverList,
+    classifier: FileClassifier,
+    dirs: Vec<PathBuf>,
+    excludes: Vec<String>,
+    jobs: usize,
+    enabled: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let names: Vec<String> = classifier.config().languages().map(String::from).collect();
+    let receiver = classify(Arc::new(driver), Arc::new(classifier), dirs, excludes, jobs,
+        Arc::clone(&stop));
+
+    let mut app = App {
+        entries: vec![],
+        enabled: names.iter().map(|l| enabled.contains(l)).collect(),
+        names,
+        show: Show::All,
+        filter: String::new(),
+        editing: false,
+        languages_focused: false,
+        files: TableState::default(),
+        languages: ListState::default().with_selected(Some(0)),
+        message: String::new(),
+    };
+
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut app, &receiver);
+    ratatui::restore();
+    stop.store(true, Ordering::Relaxed);
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    receiver: &Receiver<Entry>,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        app.entries.extend(receiver.try_iter());
+        terminal.draw(|frame| app.draw(frame))?;
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && ! app.key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
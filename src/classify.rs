@@ -0,0 +1,660 @@
+//! Deciding by extension, content, MIME type and rules which files are source code.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::with_content;
+use crate::crawl::Pattern;
+use crate::drivers::{Driver, MimeType};
+
+/// A programming language, identified by file extensions and MIME types.
+///
+/// MIME types are usually bare subtypes, see `MimeType::matches()`.
+#[derive(Debug)]
+pub struct Language {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub mimetypes: &'static [&'static str],
+}
+
+/// All supported languages.
+///
+/// MIME types are from shared-mime-info and GNU file(1), where different.
+pub const LANGUAGES: &[Language] = &[
+    Language { name: "asm", extensions: &["asm", "s", "S"], mimetypes: &[] },
+    Language { name: "c", extensions: &["c", "h"], mimetypes: &["x-chdr", "x-csrc", "x-c"] },
+    Language {
+        name: "c++",
+        extensions: &["cc", "cpp", "cxx", "hpp", "hxx"],
+        mimetypes: &["x-c++", "x-c++src", "x-c++hdr"],
+    },
+    Language { name: "csharp", extensions: &["cs"], mimetypes: &["x-csharp"] },
+    Language { name: "erlang", extensions: &["erl"], mimetypes: &["x-erlang"] },
+    Language { name: "go", extensions: &["go"], mimetypes: &[] },
+    Language { name: "java", extensions: &["java"], mimetypes: &["x-java"] },
+    Language { name: "javascript", extensions: &["js"], mimetypes: &["x-javascript"] },
+    Language { name: "lua", extensions: &["lua"], mimetypes: &["x-lua"] },
+    Language { name: "matlab", extensions: &[], mimetypes: &["x-matlab"] },
+    Language { name: "objc", extensions: &["m", "mm"], mimetypes: &["x-objc", "x-objcsrc"] },
+    Language { name: "perl", extensions: &["pl", "pm"], mimetypes: &["x-perl"] },
+    Language { name: "php", extensions: &["php"], mimetypes: &["x-php"] },
+    Language { name: "prolog", extensions: &[], mimetypes: &["x-prolog"] },
+    Language { name: "python", extensions: &["py"], mimetypes: &["x-python"] },
+    Language { name: "ruby", extensions: &["rb"], mimetypes: &["x-ruby"] },
+    Language { name: "rust", extensions: &["rs"], mimetypes: &["rust"] },
+    Language { name: "shell", extensions: &["sh"], mimetypes: &["x-shellscript"] },
+    Language { name: "tcl", extensions: &["tcl"], mimetypes: &["x-tcl"] },
+];
+
+/// Content patterns for an extension that several languages share.
+#[derive(Debug)]
+pub struct Heuristic {
+    pub extension: &'static str,
+    /// Languages and regular expressions on the start of the file,
+    /// the first match wins.
+    pub languages: &'static [(&'static str, &'static str)],
+}
+
+/// Heuristics for ambiguous extensions.
+///
+/// Without a match, the extension tables decide.
+pub const HEURISTICS: &[Heuristic] = &[
+    Heuristic { extension: "h", languages: &[
+        ("objc", r"(?m)^\s*(@interface|@protocol|@end|#import)\b"),
+        ("c++", r"(?m)^\s*(template\s*<|namespace\s+\w*\s*\{|class\s+\w+\s*[:{])|std::"),
+    ] },
+    Heuristic { extension: "m", languages: &[
+        ("objc", r"(?m)^\s*(@interface|@implementation|@protocol|@end|#import|#include)\b"),
+        ("matlab", r"(?m)^\s*(function\b|%)"),
+    ] },
+    Heuristic { extension: "pl", languages: &[
+        ("perl", r"(?m)^\s*(use\s+(strict|warnings)\b|my\s+[$@%]|sub\s+\w+|package\s+\w)|^#!.*\bperl"),
+        ("prolog", r"(?m)^\s*:-|^[a-z]\w*(\(.*\))?\s*:-"),
+    ] },
+    Heuristic { extension: "inc", languages: &[
+        ("php", r"<\?php"),
+        ("asm", r"(?mi)^\s*(%macro|%define|\.macro|\.equ|\w+\s+equ)\b"),
+        ("c", r"(?m)^\s*#\s*(define|ifn?def|include)\b"),
+    ] },
+];
+
+/// Extensions of files that are never source code, like objects, archives,
+/// images and documents. They are excluded without asking a driver.
+pub const BINARY_EXTENSIONS: &[&str] = &[
+    "o", "obj", "a", "lib", "so", "dylib", "dll", "exe", "ko", "pyc", "class", "jar",
+    "zip", "gz", "bz2", "xz", "zst", "tar", "tgz", "7z", "rar", "deb", "rpm", "iso",
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "webp", "tif", "tiff",
+    "mp3", "mp4", "ogg", "wav", "flac", "avi", "mkv", "mov",
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt",
+    "ttf", "otf", "woff", "woff2", "bin", "dat", "db", "sqlite",
+];
+
+/// Bytes at the start of a file that content checks look at.
+const PEEK_SIZE: u64 = 64 * 1024;
+
+/// Call `f` with the first `PEEK_SIZE` bytes of `path`, read into the
+/// content buffer of the thread.
+fn peek<R>(path: &Path, f: impl FnOnce(&[u8]) -> R) -> std::io::Result<R> {
+    let file = fs::File::open(path)?;
+    with_content(|content| {
+        file.take(PEEK_SIZE).read_to_end(content)?;
+        Ok(f(content))
+    })
+}
+
+/// A regular expression on the content of a file, see `Rule::content`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContentPattern(regex::bytes::Regex);
+
+impl ContentPattern {
+    /// Check if the start of `path` matches.
+    ///
+    /// Directories and unreadable files never match.
+    pub fn matches_file(&self, path: &Path) -> bool {
+        ! path.is_dir() && peek(path, |content| self.0.is_match(content)).unwrap_or(false)
+    }
+}
+
+impl PartialEq for ContentPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for ContentPattern {}
+
+impl FromStr for ContentPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        regex::bytes::Regex::new(s)
+            .map(ContentPattern)
+            .map_err(|e| format!("Invalid content pattern '{}': {}", s, e))
+    }
+}
+
+impl TryFrom<String> for ContentPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ContentPattern> for String {
+    fn from(pattern: ContentPattern) -> Self {
+        pattern.0.as_str().to_string()
+    }
+}
+
+impl fmt::Display for ContentPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+/// What a matching `Rule` decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Include,
+    Exclude,
+}
+
+/// A user-defined classification rule.
+///
+/// Rules are checked in order before the extension and MIME type tables,
+/// the first rule whose conditions all match decides.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rule {
+    /// Pattern on the path, like the excludes, e.g. `*_generated.c`.
+    pub glob: Option<Pattern>,
+    /// The whole file name, e.g. `Kconfig`.
+    pub filename: Option<String>,
+    /// The extension without the dot.
+    pub extension: Option<String>,
+    /// The MIME type, see `MimeType::matches()`. This asks the driver.
+    pub mime: Option<String>,
+    /// A regular expression on the start of the content.
+    pub content: Option<ContentPattern>,
+    pub action: Action,
+    /// The language of included files. A rule for a language that is
+    /// not selected is skipped.
+    pub language: Option<String>,
+}
+
+impl Rule {
+    /// A rule without conditions, that matches every file.
+    pub fn new(action: Action) -> Self {
+        Rule { glob: None, filename: None, extension: None, mime: None, content: None,
+            action, language: None, }
+    }
+
+    /// Check the conditions on the name of `path`.
+    fn matches_path(&self, path: &Path) -> bool {
+        self.glob.as_ref().is_none_or(|g| g.matches(path))
+            && self.filename.as_ref().is_none_or(|f| path.file_name().is_some_and(|n| n == f.as_str()))
+            && self.extension.as_ref().is_none_or(|e| path.extension().is_some_and(|x| x == e.as_str()))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut conditions = vec![];
+        let mut condition = |name: &str, value: Option<String>| if let Some(value) = value {
+            conditions.push(format!("{}={}", name, value));
+        };
+        condition("glob", self.glob.as_ref().map(Pattern::to_string));
+        condition("filename", self.filename.clone());
+        condition("extension", self.extension.clone());
+        condition("mime", self.mime.clone());
+        condition("content", self.content.as_ref().map(ContentPattern::to_string));
+        let action = match self.action {
+            Action::Include => "include",
+            Action::Exclude => "exclude",
+        };
+        match conditions.is_empty() {
+            true => write!(f, "{} all", action),
+            false => write!(f, "{} {}", action, conditions.join(" ")),
+        }
+    }
+}
+
+/// Tables to classify files by, per language.
+///
+/// The default has the tables of `LANGUAGES`. Change them to support
+/// languages like Kotlin or Zig without a new release.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassifierConfig {
+    /// Extensions without the dot for each language, the first match wins.
+    pub extensions: Vec<(String, Vec<String>)>,
+    /// MIME types for each language, see `MimeType::matches()`.
+    /// The first match wins.
+    pub mimetypes: Vec<(String, Vec<String>)>,
+    /// Content patterns and their language for extensions that several
+    /// languages share, checked before the extensions. See `HEURISTICS`.
+    pub heuristics: Vec<(String, Vec<(String, ContentPattern)>)>,
+    /// Extensions without the dot of files that are never source, in any
+    /// case. See `BINARY_EXTENSIONS`.
+    pub binary: Vec<String>,
+    /// Rules checked before the tables.
+    pub rules: Vec<Rule>,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        let patterns = |v: &[(&str, &str)]| v.iter()
+            .map(|(l, p)| (l.to_string(), p.parse().expect("Invalid heuristic.")))
+            .collect();
+        ClassifierConfig {
+            extensions: LANGUAGES.iter()
+                .map(|l| (l.name.to_string(), strings(l.extensions)))
+                .collect(),
+            mimetypes: LANGUAGES.iter()
+                .map(|l| (l.name.to_string(), strings(l.mimetypes)))
+                .collect(),
+            heuristics: HEURISTICS.iter()
+                .map(|h| (h.extension.to_string(), patterns(h.languages)))
+                .collect(),
+            binary: strings(BINARY_EXTENSIONS),
+            rules: vec![],
+        }
+    }
+}
+
+impl ClassifierConfig {
+    /// Replace the `extensions` of `language`, or add the language.
+    pub fn set_extensions(&mut self, language: &str, extensions: Vec<String>) {
+        match self.extensions.iter_mut().find(|(l, _)| l == language) {
+            Some((_, e)) => *e = extensions,
+            None => self.extensions.push((language.to_string(), extensions)),
+        }
+    }
+
+    /// Replace the `mimetypes` of `language`, or add the language.
+    pub fn set_mimetypes(&mut self, language: &str, mimetypes: Vec<String>) {
+        match self.mimetypes.iter_mut().find(|(l, _)| l == language) {
+            Some((_, m)) => *m = mimetypes,
+            None => self.mimetypes.push((language.to_string(), mimetypes)),
+        }
+    }
+
+    /// Names of all languages in the tables.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        let mut names: Vec<&str> = LANGUAGES.iter().map(|l| l.name).collect();
+        for (language, _) in self.extensions.iter().chain(&self.mimetypes) {
+            if ! names.contains(&language.as_str()) {
+                names.push(language);
+            }
+        }
+        names.into_iter()
+    }
+
+    /// The language whose extensions match `path`, if `enabled`.
+    pub fn language_by_extension(&self, path: &Path, enabled: impl Fn(&str) -> bool)
+        -> Option<&str>
+    {
+        let ext = path.extension()?;
+        self.extensions.iter()
+            .find(|(l, e)| enabled(l) && e.iter().any(|e| ext == e.as_str()))
+            .map(|(l, _)| l.as_str())
+    }
+
+    /// The language that the start of `content` looks like, if the
+    /// extension of `path` is ambiguous.
+    pub fn language_by_content(&self, path: &Path, content: &[u8]) -> Option<&str> {
+        let ext = path.extension()?;
+        self.heuristics.iter()
+            .find(|(e, _)| ext == e.as_str())?
+            .1.iter()
+            .find(|(_, p)| p.0.is_match(content))
+            .map(|(l, _)| l.as_str())
+    }
+
+    /// The language whose MIME types match `mime`, if `enabled`.
+    ///
+    /// See `MimeType::matches()` for the patterns.
+    pub fn language_by_mime(&self, mime: &MimeType, enabled: impl Fn(&str) -> bool) -> Option<&str> {
+        self.mimetypes.iter()
+            .find(|(l, m)| enabled(l) && m.iter().any(|m| mime.matches(m)))
+            .map(|(l, _)| l.as_str())
+    }
+}
+
+/// Classifies files by extension first, looking at the content of ambiguous ones,
+/// and by the MIME type from a driver second.
+#[derive(Debug, Clone)]
+pub struct FileClassifier {
+    config: ClassifierConfig,
+    languages: Option<Vec<String>>,
+    /// The first enabled language of each extension, an index into
+    /// `config.extensions`.
+    extensions: HashMap<OsString, usize>,
+    /// `config.binary` in lower case.
+    binary: HashSet<OsString>,
+}
+
+/// The result of classifying a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub decision: Decision,
+    /// The language of an included file.
+    pub language: Option<String>,
+}
+
+/// What decided whether a file is scoped.
+///
+/// Rules are identified by their index in `ClassifierConfig::rules`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    IncludedByExtension,
+    IncludedByMime(MimeType),
+    IncludedByRule(usize),
+    Excluded(Exclusion),
+}
+
+/// Why a file is not scoped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exclusion {
+    /// The MIME type belongs to no selected language.
+    Mime(MimeType),
+    Rule(usize),
+    /// The extension is one of a file that is never source.
+    Binary,
+}
+
+impl Decision {
+    pub fn include(&self) -> bool {
+        ! matches!(self, Decision::Excluded(_))
+    }
+
+    /// The rule of thumb for reports, without the details.
+    pub fn reason(&self) -> Reason {
+        match self {
+            Decision::IncludedByExtension => Reason::IncludeExtension,
+            Decision::IncludedByMime(_) => Reason::IncludeMime,
+            Decision::IncludedByRule(_) => Reason::IncludeRule,
+            Decision::Excluded(exclusion) => exclusion.reason(),
+        }
+    }
+
+    /// The MIME type, if it decided.
+    pub fn mime(&self) -> Option<&MimeType> {
+        match self {
+            Decision::IncludedByMime(mime) => Some(mime),
+            Decision::Excluded(exclusion) => exclusion.mime(),
+            _ => None,
+        }
+    }
+
+    /// The index of the deciding rule.
+    pub fn rule(&self) -> Option<usize> {
+        match self {
+            Decision::IncludedByRule(i) => Some(*i),
+            Decision::Excluded(exclusion) => exclusion.rule(),
+            _ => None,
+        }
+    }
+}
+
+impl Exclusion {
+    pub fn reason(&self) -> Reason {
+        match self {
+            Exclusion::Mime(_) => Reason::ExcludeMime,
+            Exclusion::Rule(_) => Reason::ExcludeRule,
+            Exclusion::Binary => Reason::ExcludeBinary,
+        }
+    }
+
+    pub fn mime(&self) -> Option<&MimeType> {
+        match self {
+            Exclusion::Mime(mime) => Some(mime),
+            Exclusion::Rule(_) | Exclusion::Binary => None,
+        }
+    }
+
+    pub fn rule(&self) -> Option<usize> {
+        match *self {
+            Exclusion::Rule(i) => Some(i),
+            Exclusion::Mime(_) | Exclusion::Binary => None,
+        }
+    }
+}
+
+/// The MIME type of a file, asked for at most once and only when needed.
+struct LazyMime<F> {
+    driver: Option<F>,
+    mime: Option<MimeType>,
+}
+
+impl<F: FnOnce() -> Result<MimeType, Box<dyn Error>>> LazyMime<F> {
+    fn get(&mut self) -> Result<&MimeType, Box<dyn Error>> {
+        if let Some(driver) = self.driver.take() {
+            self.mime = Some(driver()?);
+        }
+        self.mime.as_ref().ok_or_else(|| "No MIME type.".into())
+    }
+}
+
+impl Default for FileClassifier {
+    fn default() -> Self {
+        FileClassifier::new(ClassifierConfig::default())
+    }
+}
+
+impl FileClassifier {
+    /// Classify with the tables of `config`, for all languages.
+    pub fn new(config: ClassifierConfig) -> Self {
+        let binary = config.binary.iter().map(|e| OsString::from(e.to_ascii_lowercase())).collect();
+        let mut classifier = FileClassifier { config, languages: None, extensions: HashMap::new(), binary };
+        classifier.index_extensions();
+        classifier
+    }
+
+    /// Map each extension to the first enabled language that has it.
+    fn index_extensions(&mut self) {
+        let mut extensions = HashMap::new();
+        for (i, (language, exts)) in self.config.extensions.iter().enumerate() {
+            if self.enabled(language) {
+                for ext in exts {
+                    extensions.entry(OsString::from(ext)).or_insert(i);
+                }
+            }
+        }
+        self.extensions = extensions;
+    }
+
+    /// The tables to classify with.
+    pub fn config(&self) -> &ClassifierConfig {
+        &self.config
+    }
+
+    /// Restrict classification to the languages with the given `names`.
+    pub fn set_languages(&mut self, names: &[String]) -> Result<(), Box<dyn Error>> {
+        let mut languages = vec![];
+        for name in names {
+            let name = name.to_lowercase();
+            if ! self.config.languages().any(|l| l == name) {
+                return Err(format!("Unknown language '{}'.", name).into());
+            }
+            languages.push(name);
+        }
+        self.languages = Some(languages);
+        self.index_extensions();
+        Ok(())
+    }
+
+    /// Check if `language` is one to classify.
+    pub fn enabled(&self, language: &str) -> bool {
+        self.languages.as_ref().is_none_or(|l| l.iter().any(|l| l == language))
+    }
+
+    /// The name of the language whose extensions match `path`.
+    pub fn language_by_extension(&self, path: &Path) -> Option<&str> {
+        let i = *self.extensions.get(path.extension()?)?;
+        Some(&self.config.extensions[i].0)
+    }
+
+    /// Check if the extension of `path` is one of a file that is never source.
+    pub fn is_binary(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|ext| self.binary.contains(&ext.to_ascii_lowercase()))
+    }
+
+    /// The language that the content of `path` looks like, if its extension
+    /// is ambiguous, whether the language is selected or not.
+    pub fn language_by_content(&self, path: &Path) -> Option<&str> {
+        let ext = path.extension()?;
+        if ! self.config.heuristics.iter().any(|(e, _)| ext == e.as_str()) || path.is_dir() {
+            return None;
+        }
+        peek(path, |content| self.config.language_by_content(path, content)).ok()?
+    }
+
+    /// The language of `path` by its content or else by its extension.
+    ///
+    /// Content of a language that is not selected leaves the decision to
+    /// the MIME type, even if the extension belongs to a selected language.
+    fn language_by_name(&self, path: &Path) -> Option<&str> {
+        match self.language_by_content(path) {
+            Some(language) => self.enabled(language).then_some(language),
+            None => self.language_by_extension(path),
+        }
+    }
+
+    /// The name of the language whose MIME types match `mime`.
+    pub fn language_by_mime(&self, mime: &MimeType) -> Option<&str> {
+        self.config.language_by_mime(mime, |l| self.enabled(l))
+    }
+
+    /// Check if classifying `path` may need its MIME type.
+    #[cfg(feature = "async")]
+    pub(crate) fn needs_mime(&self, path: &Path) -> bool {
+        self.language_by_name(path).is_none() && ! self.is_binary(path)
+            || self.config.rules.iter().any(|r| r.mime.is_some() && r.matches_path(path))
+    }
+
+    /// Classify `path`, and ask the `driver` only if no rule or extension decides.
+    ///
+    /// Files with an extension of `ClassifierConfig::binary` are excluded
+    /// unless a rule includes them or a language has the extension.
+    pub fn classify(&self, path: &Path, driver: &dyn Driver)
+        -> Result<Classification, Box<dyn Error>>
+    {
+        self.classify_with(path, || driver.run(path))
+    }
+
+    /// Classify `path` with the MIME type from `mime`, which is called
+    /// only if no rule or extension decides without it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "classify", level = "debug", err,
+        skip_all, fields(path = %path.display())))]
+    pub fn classify_with(
+        &self,
+        path: &Path,
+        mime: impl FnOnce() -> Result<MimeType, Box<dyn Error>>,
+    ) -> Result<Classification, Box<dyn Error>> {
+        let mut mime = LazyMime { driver: Some(mime), mime: None };
+        for (i, rule) in self.config.rules.iter().enumerate() {
+            if ! rule.language.as_deref().is_none_or(|l| self.enabled(l)) || ! rule.matches_path(path) {
+                continue;
+            }
+            if let Some(ref pattern) = rule.mime {
+                if ! mime.get()?.matches(pattern) {
+                    continue;
+                }
+            }
+            if rule.content.as_ref().is_some_and(|c| ! c.matches_file(path)) {
+                continue;
+            }
+            let classification = match rule.action {
+                Action::Include => {
+                    let language = rule.language.as_deref()
+                        .or_else(|| self.language_by_name(path))
+                        .or_else(|| mime.mime.as_ref().and_then(|m| self.language_by_mime(m)))
+                        .unwrap_or("other");
+                    Classification {
+                        decision: Decision::IncludedByRule(i),
+                        language: Some(language.to_string()),
+                    }
+                },
+                Action::Exclude => Classification {
+                    decision: Decision::Excluded(Exclusion::Rule(i)),
+                    language: None,
+                },
+            };
+            return Ok(classification);
+        }
+
+        if let Some(language) = self.language_by_name(path) {
+            return Ok(Classification {
+                decision: Decision::IncludedByExtension,
+                language: Some(language.to_string()),
+            });
+        }
+        if self.is_binary(path) {
+            return Ok(Classification { decision: Decision::Excluded(Exclusion::Binary), language: None });
+        }
+        let mime = mime.get()?.clone();
+        let classification = match self.language_by_mime(&mime) {
+            Some(language) => Classification {
+                decision: Decision::IncludedByMime(mime),
+                language: Some(language.to_string()),
+            },
+            None => Classification { decision: Decision::Excluded(Exclusion::Mime(mime)), language: None },
+        };
+        Ok(classification)
+    }
+}
+
+/// Why a file is scoped or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    IncludeExtension,
+    IncludeMime,
+    ExcludeMime,
+    IncludeRule,
+    ExcludeRule,
+    ExcludeBinary,
+}
+
+impl Reason {
+    /// Whether the file is scoped.
+    #[inline]
+    pub fn include(&self) -> bool {
+        !matches!(self, Reason::ExcludeMime | Reason::ExcludeRule | Reason::ExcludeBinary)
+    }
+
+    /// The rule that made the decision.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Reason::IncludeExtension | Reason::ExcludeBinary => "extension",
+            Reason::IncludeMime | Reason::ExcludeMime => "mime",
+            Reason::IncludeRule | Reason::ExcludeRule => "rule",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Reason::IncludeExtension => "Include [.ext]",
+            Reason::IncludeMime => "Include [mime]",
+            Reason::ExcludeMime => "Exclude [----]",
+            Reason::IncludeRule => "Include [rule]",
+            Reason::ExcludeRule => "Exclude [rule]",
+            Reason::ExcludeBinary => "Exclude [.bin]",
+        })
+    }
+}
@@ -0,0 +1,1376 @@
+//! Walking the directories and queueing the files to classify.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::error::Error;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use std::ffi::{CStr, CString};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::SystemTime;
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use memchr::memmem;
+#[cfg(unix)]
+use rustix::fs::{AtFlags, Dir, Mode, OFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, HEURISTICS, LANGUAGES};
+
+/// How to present discovered paths to the user and the databases.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Paths as crawled, relative to the invocation directory.
+    #[default]
+    AsIs,
+    /// Absolute paths, without resolving symbolic links.
+    ///
+    /// `..` components are removed lexically.
+    Absolute,
+    /// Paths relative to a directory.
+    RelativeTo(PathBuf),
+}
+
+impl PathStyle {
+    /// Present `path` in this style.
+    pub fn apply<'a>(&self, path: &'a Path) -> std::borrow::Cow<'a, Path> {
+        match self {
+            PathStyle::AsIs => path.into(),
+            PathStyle::Absolute => match PathStyle::absolute(path) {
+                Ok(path) => path.into(),
+                Err(_) => path.into(),
+            },
+            PathStyle::RelativeTo(base) => {
+                match (PathStyle::absolute(base), PathStyle::absolute(path)) {
+                    (Ok(base), Ok(path)) => PathStyle::relative(&base, &path).into(),
+                    _ => path.into(),
+                }
+            },
+        }
+    }
+
+    /// Make `path` absolute and drop `.` and `..` components.
+    fn absolute(path: &Path) -> std::io::Result<PathBuf> {
+        let mut result = PathBuf::new();
+        for c in std::path::absolute(path)?.components() {
+            match c {
+                Component::CurDir => {},
+                Component::ParentDir => {
+                    result.pop();
+                },
+                c => result.push(c),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Express absolute `path` relative to absolute `base`.
+    fn relative(base: &Path, path: &Path) -> PathBuf {
+        let mut base = base.components().peekable();
+        let mut path = path.components().peekable();
+        while let (Some(b), Some(p)) = (base.peek(), path.peek()) {
+            if b != p {
+                break;
+            }
+            base.next();
+            path.next();
+        }
+
+        let mut result: PathBuf = base.map(|_| Component::ParentDir).collect();
+        result.extend(path);
+        if result.as_os_str().is_empty() {
+            result.push(Component::CurDir);
+        }
+        result
+    }
+}
+
+/// Excludes that always apply, for version control metadata.
+///
+/// Like all patterns, their `/` matches `\` as well on Windows.
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "/.git/",
+    "/.svn/",
+    "/CVS/",
+];
+
+/// Order in which queued files are classified and indexed.
+///
+/// On long runs the order decides which files a partial database has first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Schedule {
+    /// In the order crawled.
+    #[default]
+    Fifo,
+    /// Most recently crawled first.
+    Lifo,
+    /// Smallest files first, for many files early.
+    SmallestFirst,
+    /// Largest files first, for the big ones early. They are spread
+    /// across the workers instead of leaving one with a long tail.
+    LargestFirst,
+    /// Files with a known source extension first, smallest first,
+    /// so that the databases are useful early in long builds.
+    Progressive,
+}
+
+impl Schedule {
+    /// Names of all policies, as accepted by `from_str()`.
+    pub const NAMES: &'static [&'static str] =
+        &["fifo", "lifo", "smallest-first", "largest-first", "progressive"];
+
+    /// The rank of a file of `size` bytes, the lowest is indexed first.
+    fn rank(self, path: &Path, size: u64) -> (bool, u64) {
+        match self {
+            Schedule::LargestFirst => (false, u64::MAX - size),
+            Schedule::Progressive => (! Schedule::known_source(path), size),
+            _ => (false, size),
+        }
+    }
+
+    /// Check if `path` has the extension of a built-in language.
+    fn known_source(path: &Path) -> bool {
+        let Some(extension) = path.extension() else {
+            return false;
+        };
+        LANGUAGES.iter().any(|l| l.extensions.iter().any(|e| extension == *e))
+            || HEURISTICS.iter().any(|h| extension == h.extension)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Schedule::Fifo),
+            "lifo" => Ok(Schedule::Lifo),
+            "smallest-first" => Ok(Schedule::SmallestFirst),
+            "largest-first" => Ok(Schedule::LargestFirst),
+            "progressive" => Ok(Schedule::Progressive),
+            _ => Err(format!("Unknown schedule '{}'.", s)),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Schedule::Fifo => "fifo",
+            Schedule::Lifo => "lifo",
+            Schedule::SmallestFirst => "smallest-first",
+            Schedule::LargestFirst => "largest-first",
+            Schedule::Progressive => "progressive",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The files between the crawler and the workers.
+///
+/// In FIFO order, each worker takes batches of files into its own deque and
+/// steals from the others when it runs dry, so that many workers do not take
+/// turns on one lock. LIFO keeps a stack, the size schedules keep a heap
+/// ordered by the size of each file when it was queued.
+///
+/// Beyond the limit of `spill_after()`, files go to a temporary file and
+/// come back when the queue in memory runs dry. Schedules other than FIFO
+/// only order the files in memory then.
+pub struct FileQueue {
+    schedule: Schedule,
+    repr: Repr,
+    /// Most files in memory before spilling.
+    limit: usize,
+    spill: Mutex<Option<Spill>>,
+    /// Files in the spill, to check without the lock.
+    spilled: AtomicUsize,
+    /// Files queued so far, to keep files of the same rank in order.
+    pushed: AtomicU64,
+    cache: Option<Arc<MetadataCache>>,
+}
+
+/// A queued file of a size schedule, ordered by rank and then as crawled.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Ranked {
+    rank: (bool, u64),
+    seq: u64,
+    path: PathBuf,
+}
+
+enum Repr {
+    /// LIFO.
+    Stack(Mutex<Vec<PathBuf>>),
+    /// The size schedules.
+    Ranked(Mutex<BinaryHeap<Reverse<Ranked>>>),
+    Stealing {
+        injector: Box<Injector<PathBuf>>,
+        stealers: Vec<Stealer<PathBuf>>,
+        /// The deques of the stealers, until the workers take them.
+        deques: Mutex<Vec<Worker<PathBuf>>>,
+    },
+}
+
+impl FileQueue {
+    /// A queue in the order of `schedule` for up to `workers` workers.
+    pub fn new(schedule: Schedule, workers: usize) -> Self {
+        let repr = match schedule {
+            Schedule::Fifo => {
+                let deques: Vec<_> = (0..workers).map(|_| Worker::new_fifo()).collect();
+                Repr::Stealing {
+                    injector: Box::new(Injector::new()),
+                    stealers: deques.iter().map(Worker::stealer).collect(),
+                    deques: Mutex::new(deques),
+                }
+            },
+            Schedule::Lifo => Repr::Stack(Mutex::new(vec![])),
+            _ => Repr::Ranked(Mutex::new(BinaryHeap::new())),
+        };
+        FileQueue { schedule, repr, limit: usize::MAX, spill: Mutex::new(None), spilled: AtomicUsize::new(0),
+            pushed: AtomicU64::new(0), cache: None }
+    }
+
+    /// Keep at most `limit` files in memory and spill the others to a
+    /// temporary file, for trees with tens of millions of files.
+    ///
+    /// Files stay in memory if the temporary file cannot be written.
+    pub fn spill_after(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Take the sizes for the size schedules from `cache`.
+    pub fn cache(mut self, cache: Arc<MetadataCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        self.schedule
+    }
+
+    /// Make room for `files` more files in memory, up to the limit,
+    /// like after `FileCrawler::count()`.
+    pub fn reserve(&self, files: usize) {
+        let files = files.min(self.limit);
+        match self.repr {
+            Repr::Stack(ref stack) => stack.lock().unwrap().reserve(files),
+            Repr::Ranked(ref heap) => heap.lock().unwrap().reserve(files),
+            // The injector grows in blocks.
+            Repr::Stealing { .. } => (),
+        }
+    }
+
+    /// Queue `path`.
+    pub fn push(&self, path: PathBuf) {
+        // Once files are spilled, later ones follow them.
+        if self.spilled.load(Ordering::Relaxed) > 0 || self.in_memory() >= self.limit {
+            let mut spill = self.spill.lock().unwrap();
+            if spill.is_none() {
+                *spill = Spill::create().ok();
+            }
+            if let Some(ref mut spill) = *spill {
+                if spill.push(&path).is_ok() {
+                    self.spilled.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        self.push_memory(path);
+    }
+
+    fn push_memory(&self, path: PathBuf) {
+        match self.repr {
+            Repr::Stack(ref files) => files.lock().unwrap().push(path),
+            Repr::Ranked(ref files) => {
+                // One stat() per file, outside of the lock.
+                let metadata = match self.cache {
+                    Some(ref cache) => cache.metadata(&path),
+                    None => fs::metadata(&path),
+                };
+                let size = metadata.map_or(0, |m| m.len());
+                let rank = self.schedule.rank(&path, size);
+                let seq = self.pushed.fetch_add(1, Ordering::Relaxed);
+                files.lock().unwrap().push(Reverse(Ranked { rank, seq, path }));
+            },
+            Repr::Stealing { ref injector, .. } => injector.push(path),
+        }
+    }
+
+    /// Move spilled files back to memory, up to half the limit.
+    fn unspill(&self) {
+        let mut spill = self.spill.lock().unwrap();
+        let Some(ref mut spill) = *spill else {
+            return;
+        };
+        let files = match spill.pop(self.limit.div_ceil(2)) {
+            Ok(files) => files,
+            // The spilled files are lost, but the queue goes on.
+            Err(_) => {
+                spill.clear();
+                vec![]
+            },
+        };
+        self.spilled.store(spill.len, Ordering::Relaxed);
+        for path in files {
+            self.push_memory(path);
+        }
+    }
+
+    /// Number of queued files, including those taken by workers
+    /// but not processed yet.
+    pub fn len(&self) -> usize {
+        self.in_memory() + self.spilled.load(Ordering::Relaxed)
+    }
+
+    /// Number of files in memory.
+    fn in_memory(&self) -> usize {
+        match self.repr {
+            Repr::Stack(ref files) => files.lock().unwrap().len(),
+            Repr::Ranked(ref files) => files.lock().unwrap().len(),
+            Repr::Stealing { ref injector, ref stealers, .. } => {
+                injector.len() + stealers.iter().map(Stealer::len).sum::<usize>()
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A handle for one worker to take files.
+    ///
+    /// Panics if more workers ask than the queue was created for.
+    pub fn worker(self: &Arc<Self>) -> QueueWorker {
+        let deque = match self.repr {
+            Repr::Stack(_) | Repr::Ranked(_) => None,
+            Repr::Stealing { ref deques, .. } => {
+                Some(deques.lock().unwrap().pop().expect("More workers than the queue has deques."))
+            },
+        };
+        QueueWorker { queue: Arc::clone(self), deque }
+    }
+}
+
+impl fmt::Debug for FileQueue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileQueue")
+            .field("schedule", &self.schedule)
+            .field("len", &self.len())
+            .field("spilled", &self.spilled.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// One worker's end of a `FileQueue`.
+pub struct QueueWorker {
+    queue: Arc<FileQueue>,
+    deque: Option<Worker<PathBuf>>,
+}
+
+impl QueueWorker {
+    /// Take the next file, `None` if the queue is empty right now.
+    pub fn pop(&self) -> Option<PathBuf> {
+        self.pop_memory().or_else(|| {
+            if self.queue.spilled.load(Ordering::Relaxed) == 0 {
+                return None;
+            }
+            self.queue.unspill();
+            self.pop_memory()
+        })
+    }
+
+    fn pop_memory(&self) -> Option<PathBuf> {
+        let (deque, injector, stealers) = match (&self.deque, &self.queue.repr) {
+            (Some(deque), Repr::Stealing { injector, stealers, .. }) => (deque, injector, stealers),
+            (_, Repr::Stack(files)) => return files.lock().unwrap().pop(),
+            (_, Repr::Ranked(files)) => return files.lock().unwrap().pop().map(|Reverse(f)| f.path),
+            _ => return None,
+        };
+        if let Some(path) = deque.pop() {
+            return Some(path);
+        }
+        loop {
+            let steal = injector.steal_batch_and_pop(deque)
+                .or_else(|| stealers.iter().map(Stealer::steal).collect());
+            match steal {
+                Steal::Success(path) => return Some(path),
+                Steal::Empty => return None,
+                Steal::Retry => continue,
+            }
+        }
+    }
+}
+
+/// Files beyond the memory limit of a `FileQueue`, in a temporary file.
+///
+/// Paths are NUL-terminated, they cannot contain NUL.
+struct Spill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    /// Files written and not read back yet.
+    len: usize,
+}
+
+impl Spill {
+    fn create() -> io::Result<Self> {
+        static SPILLS: AtomicUsize = AtomicUsize::new(0);
+        let n = SPILLS.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("scope-{}-{}.queue", process::id(), n));
+        let writer = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let reader = File::open(&path)?;
+        Ok(Spill { path, writer: BufWriter::new(writer), reader: BufReader::new(reader), len: 0 })
+    }
+
+    fn push(&mut self, path: &Path) -> io::Result<()> {
+        self.writer.write_all(path.as_os_str().as_encoded_bytes())?;
+        self.writer.write_all(b"\0")?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Read back the oldest `n` files at most.
+    fn pop(&mut self, n: usize) -> io::Result<Vec<PathBuf>> {
+        self.writer.flush()?;
+        let mut files = vec![];
+        let mut line = vec![];
+        while files.len() < n && self.len > 0 {
+            line.clear();
+            if self.reader.read_until(b'\0', &mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            line.pop();
+            // SAFETY: The bytes were written by `push()` of this process.
+            files.push(PathBuf::from(unsafe { OsStr::from_encoded_bytes_unchecked(&line) }));
+            self.len -= 1;
+        }
+        if self.len == 0 {
+            self.clear();
+        }
+        Ok(files)
+    }
+
+    /// Forget all files and start over with an empty file.
+    fn clear(&mut self) {
+        self.len = 0;
+        self.writer.seek(SeekFrom::Start(0))
+            .and_then(|_| self.writer.get_mut().set_len(0))
+            .and_then(|_| self.reader.seek(SeekFrom::Start(0)).map(drop))
+            .unwrap_or_default();
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).unwrap_or_default();
+    }
+}
+
+/// Decides whether the crawler skips a file, in addition to the excludes.
+///
+/// Filters see regular files only, directories are still crawled.
+pub trait PathFilter: Send + Sync {
+    /// Check if `path` with `metadata` is to be skipped.
+    fn skip(&self, path: &Path, metadata: &fs::Metadata) -> bool;
+
+    /// Name of the filter in `FileCrawler::exclusions()`.
+    fn name(&self) -> &str {
+        "filter"
+    }
+}
+
+impl<F: Fn(&Path, &fs::Metadata) -> bool + Send + Sync> PathFilter for F {
+    fn skip(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        self(path, metadata)
+    }
+}
+
+impl fmt::Debug for dyn PathFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PathFilter({})", self.name())
+    }
+}
+
+/// Skip files larger than the given number of bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxSize(pub u64);
+
+impl PathFilter for MaxSize {
+    fn skip(&self, _path: &Path, metadata: &fs::Metadata) -> bool {
+        metadata.len() > self.0
+    }
+
+    fn name(&self) -> &str {
+        "size"
+    }
+}
+
+/// Skip files last modified before the given time.
+#[derive(Debug, Clone, Copy)]
+pub struct ModifiedSince(pub SystemTime);
+
+impl PathFilter for ModifiedSince {
+    fn skip(&self, _path: &Path, metadata: &fs::Metadata) -> bool {
+        metadata.modified().is_ok_and(|t| t < self.0)
+    }
+
+    fn name(&self) -> &str {
+        "--since"
+    }
+}
+
+/// Skip files not owned by the given user ID.
+#[derive(Debug, Clone, Copy)]
+pub struct Owner(pub u32);
+
+impl Owner {
+    /// Files of the user `uid`, an error where files have no user IDs.
+    pub fn new(uid: u32) -> io::Result<Self> {
+        if cfg!(unix) {
+            Ok(Owner(uid))
+        } else {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "Files have no owner IDs on this system."))
+        }
+    }
+}
+
+impl PathFilter for Owner {
+    #[cfg(unix)]
+    fn skip(&self, _path: &Path, metadata: &fs::Metadata) -> bool {
+        metadata.uid() != self.0
+    }
+
+    /// No file is known to be owned by the user, see `Owner::new()`.
+    #[cfg(not(unix))]
+    fn skip(&self, _path: &Path, _metadata: &fs::Metadata) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        "owner"
+    }
+}
+
+/// The type of a path, as far as the crawler is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Directory,
+    File,
+    Symlink,
+    Other,
+}
+
+impl Kind {
+    fn from_std(file_type: fs::FileType) -> Self {
+        match file_type {
+            t if t.is_dir() => Kind::Directory,
+            t if t.is_file() => Kind::File,
+            t if t.is_symlink() => Kind::Symlink,
+            _ => Kind::Other,
+        }
+    }
+
+    /// The kind of `file_type`, if the filesystem told it.
+    #[cfg(unix)]
+    fn from_rustix(file_type: rustix::fs::FileType) -> Option<Self> {
+        use rustix::fs::FileType;
+        match file_type {
+            FileType::Directory => Some(Kind::Directory),
+            FileType::RegularFile => Some(Kind::File),
+            FileType::Symlink => Some(Kind::Symlink),
+            FileType::Unknown => None,
+            _ => Some(Kind::Other),
+        }
+    }
+}
+
+/// A path for the crawler to visit.
+#[derive(Debug, Clone)]
+struct Visit {
+    path: PathBuf,
+    /// The type, if the directory entry told it.
+    kind: Option<Kind>,
+    /// The directory `path` is in, to open and stat it by name only.
+    #[cfg(unix)]
+    parent: Option<Arc<OwnedFd>>,
+}
+
+impl Visit {
+    fn new(path: PathBuf, kind: Option<Kind>) -> Self {
+        Visit {
+            path,
+            kind,
+            #[cfg(unix)]
+            parent: None,
+        }
+    }
+
+    fn root(path: PathBuf) -> Self {
+        Visit::new(path, None)
+    }
+
+    /// The directory to resolve the path in, and the path relative to it.
+    ///
+    /// Deep paths are not resolved again for each child this way.
+    #[cfg(unix)]
+    fn at(&self) -> (BorrowedFd<'_>, &Path) {
+        match (&self.parent, self.path.file_name()) {
+            (Some(parent), Some(name)) => (parent.as_fd(), Path::new(name)),
+            _ => (rustix::fs::CWD, &self.path),
+        }
+    }
+
+    /// The type of the path, following a symbolic link.
+    #[cfg(unix)]
+    fn stat(&self) -> io::Result<Kind> {
+        let (dir, path) = self.at();
+        let stat = rustix::fs::statat(dir, path, AtFlags::empty())?;
+        Ok(Kind::from_rustix(rustix::fs::FileType::from_raw_mode(stat.st_mode)).unwrap_or(Kind::Other))
+    }
+
+    /// The entries of the directory, opened relative to its parent.
+    #[cfg(unix)]
+    fn read_dir(&self) -> io::Result<Vec<Visit>> {
+        let (dir, path) = self.at();
+        let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC;
+        let fd = rustix::fs::openat(dir, path, flags, Mode::empty())?;
+        let mut children = vec![];
+        for entry in Dir::new(rustix::io::dup(&fd)?)? {
+            let entry = entry?;
+            let name = entry.file_name().to_bytes();
+            if name == b"." || name == b".." {
+                continue;
+            }
+            children.push(Visit::new(self.path.join(OsStr::from_bytes(name)), Kind::from_rustix(entry.file_type())));
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        Visit::resolve(&fd, &mut children);
+        let fd = Arc::new(fd);
+        for child in &mut children {
+            child.parent = Some(Arc::clone(&fd));
+        }
+        Ok(children)
+    }
+
+    /// The type of the path, following a symbolic link.
+    #[cfg(not(unix))]
+    fn stat(&self) -> io::Result<Kind> {
+        fs::metadata(&self.path).map(|metadata| Kind::from_std(metadata.file_type()))
+    }
+
+    /// The entries of the directory, by path.
+    #[cfg(not(unix))]
+    fn read_dir(&self) -> io::Result<Vec<Visit>> {
+        read_dir(&self.path)
+    }
+
+    /// Stat the `children` of the directory `dir` that its entries did not
+    /// tell the type of, and symbolic links, in batches on io_uring.
+    ///
+    /// Children that cannot be stated are gone or dangling links, and
+    /// dropped. Without io_uring, `visit()` stats them one by one.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn resolve(dir: &OwnedFd, children: &mut Vec<Visit>) {
+        let unresolved: Vec<usize> = (0..children.len())
+            .filter(|&i| matches!(children[i].kind, None | Some(Kind::Symlink)))
+            .collect();
+        if unresolved.is_empty() {
+            return;
+        }
+        let names: Vec<CString> = unresolved.iter()
+            .filter_map(|&i| CString::new(children[i].path.file_name()?.as_bytes()).ok())
+            .collect();
+        if names.len() != unresolved.len() {
+            return;
+        }
+        let names: Vec<&CStr> = names.iter().map(CString::as_c_str).collect();
+        let Some(modes) = crate::uring::stat_modes(dir.as_fd(), &names) else {
+            return;
+        };
+        let mut gone = vec![false; children.len()];
+        for (i, mode) in unresolved.into_iter().zip(modes) {
+            match mode {
+                Ok(mode) => children[i].kind = Some(Kind::from_rustix(rustix::fs::FileType::from_raw_mode(mode))
+                    .unwrap_or(Kind::Other)),
+                Err(_) => gone[i] = true,
+            }
+        }
+        let mut i = 0;
+        children.retain(|_| {
+            i += 1;
+            ! gone[i - 1]
+        });
+    }
+}
+
+/// The entries of a directory, with its modification time when read.
+type Listing = (SystemTime, Vec<Visit>);
+
+/// The entries of the directory `path`, by path.
+fn read_dir(path: &Path) -> io::Result<Vec<Visit>> {
+    let mut children = vec![];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let kind = entry.file_type().ok().map(Kind::from_std);
+        children.push(Visit::new(entry.path(), kind));
+    }
+    Ok(children)
+}
+
+/// Metadata, and optionally directory listings, read by the crawler and
+/// the queue, so that network filesystems see each metadata call once.
+///
+/// Metadata is valid in the generation it was read in, each crawl of a
+/// `Scope` starts a new one. A listing is kept across generations until
+/// the modification time of its directory changes.
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    generation: AtomicU64,
+    metadata: Mutex<HashMap<PathBuf, (u64, fs::Metadata)>>,
+    listings: Option<Mutex<HashMap<PathBuf, Listing>>>,
+}
+
+impl MetadataCache {
+    /// A cache of metadata only.
+    pub fn new() -> Self {
+        MetadataCache::default()
+    }
+
+    /// Cache directory listings too.
+    pub fn with_listings(mut self) -> Self {
+        self.listings = Some(Mutex::new(HashMap::new()));
+        self
+    }
+
+    /// The current generation.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Start a new generation, e.g. for the next crawl.
+    pub fn advance(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The metadata of `path`, following symbolic links like `fs::metadata()`.
+    pub fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        let generation = self.generation();
+        if let Some((g, metadata)) = self.metadata.lock().unwrap().get(path) {
+            if *g == generation {
+                return Ok(metadata.clone());
+            }
+        }
+        let metadata = fs::metadata(path)?;
+        self.metadata.lock().unwrap().insert(path.to_path_buf(), (generation, metadata.clone()));
+        Ok(metadata)
+    }
+
+    /// Forget `path` after it changed, and the listing of its directory.
+    pub fn invalidate(&self, path: &Path) {
+        self.metadata.lock().unwrap().remove(path);
+        if let Some(ref listings) = self.listings {
+            let mut listings = listings.lock().unwrap();
+            listings.remove(path);
+            if let Some(parent) = path.parent() {
+                listings.remove(parent);
+            }
+        }
+    }
+
+    /// The entries of the directory `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<Visit>> {
+        let Some(ref listings) = self.listings else {
+            return read_dir(path);
+        };
+        let modified = self.metadata(path)?.modified()?;
+        if let Some((m, children)) = listings.lock().unwrap().get(path) {
+            if *m == modified {
+                return Ok(children.clone());
+            }
+        }
+        let children = read_dir(path)?;
+        listings.lock().unwrap().insert(path.to_path_buf(), (modified, children.clone()));
+        Ok(children)
+    }
+}
+
+/// Options for the `FileCrawler`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CrawlerOptions {
+    /// Patterns to skip, see `FileCrawler::exclude_match()`.
+    pub excludes: Vec<String>,
+    /// Number of threads to crawl with.
+    pub threads: usize,
+    /// Skip files last modified before this time.
+    pub since: Option<SystemTime>,
+    /// Order of the queued files.
+    pub schedule: Schedule,
+    /// Most queued files in memory, see `FileQueue::spill_after()`.
+    pub queue_limit: Option<usize>,
+}
+
+impl Default for CrawlerOptions {
+    fn default() -> Self {
+        CrawlerOptions { excludes: vec![], threads: 1, since: None, schedule: Schedule::Fifo, queue_limit: None }
+    }
+}
+
+/// Where the crawler puts the paths it does not skip.
+#[derive(Clone, Copy)]
+enum Sink<'a> {
+    Queue,
+    Count(&'a AtomicUsize),
+}
+
+/// File crawler that populates the list of files to scan.
+///
+/// After creation, feels like a std::thread.
+pub struct FileCrawler {
+    paths: Vec<PathBuf>,
+    excludes: Vec<String>,
+    /// The compiled `excludes`, and whether each is negated.
+    patterns: Vec<(bool, Pattern)>,
+    files: Arc<FileQueue>,
+    excluded: AtomicUsize,
+    filters: Vec<Arc<dyn PathFilter>>,
+    cancel: Arc<AtomicBool>,
+    drain: Arc<AtomicBool>,
+    exclusions: Mutex<HashMap<String, usize>>,
+    threads: usize,
+    negations: bool,
+    cache: Option<Arc<MetadataCache>>,
+}
+
+impl FileCrawler {
+    pub fn new(
+        paths: Vec<PathBuf>,
+        excludes: Vec<String>,
+        files: Arc<FileQueue>,
+    ) -> Self {
+        FileCrawler::with_options(paths, CrawlerOptions { excludes, ..CrawlerOptions::default() }, files)
+    }
+
+    /// Create a crawler with all `options` at once.
+    ///
+    /// The files are queued in the schedule and limit of `files`, not of `options`.
+    pub fn with_options(
+        paths: Vec<PathBuf>,
+        options: CrawlerOptions,
+        files: Arc<FileQueue>,
+    ) -> Self {
+        let CrawlerOptions { excludes, threads, since, .. } = options;
+        let negations = excludes.iter().any(|x| x.starts_with('!'));
+        let patterns = excludes.iter()
+            .map(|x| match x.strip_prefix('!') {
+                Some(pattern) => (true, Pattern::compile(pattern)),
+                None => (false, Pattern::compile(x)),
+            })
+            .collect();
+        let filters = since.map(|since| Arc::new(ModifiedSince(since)) as Arc<dyn PathFilter>);
+        FileCrawler { paths, excludes, patterns, files, excluded: AtomicUsize::new(0), negations,
+            filters: filters.into_iter().collect(),
+            cancel: Arc::new(AtomicBool::new(false)), drain: Arc::new(AtomicBool::new(false)),
+            exclusions: Mutex::new(HashMap::new()),
+            threads: threads.max(1), cache: None }
+    }
+
+    /// Crawl with `threads` threads, one by default.
+    ///
+    /// Directories are read in parallel, the order of files is not stable then.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Stop crawling as soon as `cancel` is set.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+
+    /// Stop crawling as soon as `drain` is set, like `cancel`, for consumers
+    /// that go on with the files queued so far.
+    pub fn set_drain(&mut self, drain: Arc<AtomicBool>) {
+        self.drain = drain;
+    }
+
+    /// Read metadata and directories through `cache`.
+    pub fn set_cache(&mut self, cache: Arc<MetadataCache>) {
+        self.cache = Some(cache);
+    }
+
+    /// Skip files last modified before `since`.
+    ///
+    /// Directories are still crawled.
+    pub fn set_since(&mut self, since: SystemTime) {
+        self.add_filter(Arc::new(ModifiedSince(since)));
+    }
+
+    /// Skip the files `filter` skips, after the excludes and earlier filters.
+    pub fn add_filter(&mut self, filter: Arc<dyn PathFilter>) {
+        self.filters.push(filter);
+    }
+
+    /// The filter that skips the file `path`, if any.
+    ///
+    /// Takes the `metadata` of `path` if it is at hand already.
+    fn filter_match(&self, path: &Path, metadata: Option<fs::Metadata>) -> Option<&str> {
+        if self.filters.is_empty() {
+            return None;
+        }
+        let metadata = match metadata {
+            Some(metadata) => metadata,
+            None => self.metadata(path).ok()?,
+        };
+        self.filters.iter()
+            .find(|filter| filter.skip(path, &metadata))
+            .map(|filter| filter.name())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        match self.cache {
+            Some(ref cache) => cache.metadata(path),
+            None => fs::metadata(path),
+        }
+    }
+
+    /// Number of paths skipped because of the excludes or filters.
+    pub fn excluded(&self) -> usize {
+        self.excluded.load(Ordering::Relaxed)
+    }
+
+    /// Number of skipped paths per exclude or filter name, `--since` for those too old.
+    pub fn exclusions(&self) -> HashMap<String, usize> {
+        self.exclusions.lock().unwrap().clone()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "crawl", level = "info", err,
+        skip_all, fields(roots = self.paths.len(), threads = self.threads)))]
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        self.walk(Sink::Queue)
+    }
+
+    /// Count the paths that `run()` would queue, without queuing them.
+    ///
+    /// The exclusions are not counted, so a later `run()` reports them once.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "count", level = "info", err,
+        skip_all, fields(roots = self.paths.len(), threads = self.threads)))]
+    pub fn count(&self) -> Result<usize, Box<dyn Error>> {
+        let count = AtomicUsize::new(0);
+        self.walk(Sink::Count(&count))?;
+        Ok(count.into_inner())
+    }
+
+    fn walk(&self, sink: Sink) -> Result<(), Box<dyn Error>> {
+        if self.threads > 1 {
+            return self.crawl_parallel(sink);
+        }
+        for path in &self.paths {
+            self.crawl(&Visit::root(path.clone()), sink)?;
+        };
+        Ok(())
+    }
+
+    /// The exclude that decides to skip `path`, if any.
+    ///
+    /// The last matching pattern wins, so that a later `!pattern`
+    /// re-includes what an earlier one excluded, like in gitignore.
+    pub fn exclude_match(&self, path: &Path) -> Option<&str> {
+        let mut result = None;
+        for (x, (negated, pattern)) in self.excludes.iter().zip(&self.patterns) {
+            if pattern.matches(path) {
+                result = (! negated).then_some(x.as_str());
+            }
+        }
+        result
+    }
+
+    /// Check if the excludes skip `path` or one of its directories.
+    pub(crate) fn exclude_path(&self, path: &Path) -> bool {
+        match self.negations {
+            // Excluded directories are still crawled then.
+            true => self.exclude_match(path).is_some(),
+            false => path.ancestors().any(|p| self.exclude_match(p).is_some()),
+        }
+    }
+
+    fn crawl(&self, visit: &Visit, sink: Sink) -> Result<(), Box<dyn Error>> {
+        for child in self.visit(visit, sink)? {
+            self.crawl(&child, sink)?;
+        }
+        Ok(())
+    }
+
+    /// Crawl with `self.threads` threads sharing a stack of paths to visit.
+    fn crawl_parallel(&self, sink: Sink) -> Result<(), Box<dyn Error>> {
+        struct Pending {
+            paths: Vec<Visit>,
+            busy: usize,
+            error: Option<String>,
+        }
+        let pending = Mutex::new(Pending {
+            paths: self.paths.iter().rev().cloned().map(Visit::root).collect(),
+            busy: 0,
+            error: None,
+        });
+        let changed = Condvar::new();
+
+        thread::scope(|s| {
+            for _ in 0..self.threads {
+                s.spawn(|| loop {
+                    let mut guard = pending.lock().unwrap();
+                    let visit = loop {
+                        if guard.error.is_some() {
+                            return;
+                        }
+                        if let Some(path) = guard.paths.pop() {
+                            guard.busy += 1;
+                            break path;
+                        }
+                        if guard.busy == 0 {
+                            return; // Done
+                        }
+                        guard = changed.wait(guard).unwrap();
+                    };
+                    drop(guard);
+
+                    let result = self.visit(&visit, sink);
+                    let mut guard = pending.lock().unwrap();
+                    guard.busy -= 1;
+                    match result {
+                        Ok(children) => guard.paths.extend(children.into_iter().rev()),
+                        Err(e) => guard.error = Some(e.to_string()),
+                    }
+                    changed.notify_all();
+                });
+            }
+        });
+
+        match pending.into_inner().unwrap().error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Put the path of `visit` into `sink` unless it is excluded, and return its children.
+    ///
+    /// The type from the directory entry saves a stat, roots and symbolic
+    /// links are followed with one. Without a cache, directories are opened
+    /// and entries stated relative to the descriptor of their parent.
+    fn visit(&self, visit: &Visit, sink: Sink) -> Result<Vec<Visit>, Box<dyn Error>> {
+        let mut children = vec![];
+        if self.cancel.load(Ordering::Relaxed) || self.drain.load(Ordering::Relaxed) {
+            return Ok(children);
+        }
+        let path = visit.path.as_path();
+        let mut metadata = None;
+        let kind = match visit.kind {
+            Some(kind) if kind != Kind::Symlink => Ok(kind),
+            _ => match self.cache {
+                Some(ref cache) => cache.metadata(path).map(|m| Kind::from_std(metadata.insert(m).file_type())),
+                None => visit.stat(),
+            },
+        };
+        let Ok(kind) = kind else {
+            return Ok(children); // Gone, or a dangling link
+        };
+        let reason = self.exclude_match(path)
+            .or_else(|| (kind == Kind::File).then(|| self.filter_match(path, metadata)).flatten());
+        if let Some(reason) = reason {
+            if let Sink::Queue = sink {
+                self.excluded.fetch_add(1, Ordering::Relaxed);
+                let mut exclusions = self.exclusions.lock().unwrap();
+                match exclusions.get_mut(reason) {
+                    Some(n) => *n += 1,
+                    None => {
+                        exclusions.insert(reason.to_string(), 1);
+                    },
+                }
+            }
+            // A negation may re-include something below.
+            if ! (self.negations && kind == Kind::Directory) {
+                return Ok(children);
+            }
+        } else {
+            match sink {
+                Sink::Queue => self.files.push(path.to_path_buf()),
+                Sink::Count(count) => {
+                    count.fetch_add(1, Ordering::Relaxed);
+                },
+            }
+        }
+        if kind == Kind::Directory {
+            let listing = match self.cache {
+                Some(ref cache) => cache.read_dir(path),
+                None => visit.read_dir(),
+            };
+            children = listing.with_context(|| format!("Cannot read directory {}", path.display()))?;
+        }
+
+        Ok(children)
+    }
+}
+
+/// A compiled exclude or rule pattern on the bytes of a path.
+///
+/// `/` is the separator of patterns and paths, on Windows `\` is turned
+/// into `/` in both. Plain patterns match any substring of the path. Patterns with `*` or `?`
+/// are globs that match the trailing components of the path, where `*` stays
+/// within a component and `**` does not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern {
+    source: String,
+    matcher: Matcher,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Plain(memmem::Finder<'static>),
+    /// The glob, and the longest text without wildcards that any match
+    /// contains, to reject most paths without trying the glob.
+    Glob(Box<[u8]>, Option<memmem::Finder<'static>>),
+}
+
+impl Pattern {
+    /// Compile `pattern` without checking it, see `FromStr` for that.
+    pub(crate) fn compile(pattern: &str) -> Self {
+        let bytes = &*separated(pattern.as_bytes(), BACKSLASH_SEPARATES);
+        let matcher = match is_glob(pattern) {
+            false => Matcher::Plain(memmem::Finder::new(bytes).into_owned()),
+            true => {
+                let literal = bytes.split(|b| matches!(b, b'*' | b'?')).max_by_key(|l| l.len())
+                    .filter(|l| ! l.is_empty())
+                    .map(|l| memmem::Finder::new(l).into_owned());
+                Matcher::Glob(bytes.into(), literal)
+            },
+        };
+        Pattern { source: pattern.to_string(), matcher }
+    }
+
+    /// Parse an exclude, with a leading `!` that re-includes what it matches.
+    pub fn exclude(exclude: &str) -> Result<(bool, Pattern), String> {
+        match exclude.strip_prefix('!') {
+            Some(pattern) => Ok((true, pattern.parse()?)),
+            None => Ok((false, exclude.parse()?)),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check if the pattern matches the bytes of `path`.
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = &*separated(path.as_os_str().as_encoded_bytes(), BACKSLASH_SEPARATES);
+        match self.matcher {
+            Matcher::Plain(ref finder) => finder.find(path).is_some(),
+            Matcher::Glob(ref glob, ref literal) => {
+                literal.as_ref().is_none_or(|l| l.find(path).is_some())
+                    && (0..path.len()).filter(|i| *i == 0 || path[i - 1] == b'/' || path[*i] == b'/')
+                        .any(|i| glob_match(glob, &path[i..]))
+            },
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Pattern {}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Compile `s`, rejecting patterns that cannot be meant as written.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let problem = match s {
+            "" => Some("an empty pattern matches every path"),
+            _ if s.contains("***") => Some("use `**` to match across directories"),
+            _ if s.contains('\0') => Some("paths never contain NUL"),
+            _ => None,
+        };
+        match problem {
+            Some(problem) => Err(format!("Invalid pattern '{}': {}.", s, problem)),
+            None => Ok(Pattern::compile(s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.source
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+pub(crate) fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Whether `\` separates path components as well as `/`.
+const BACKSLASH_SEPARATES: bool = cfg!(windows);
+
+/// `bytes` with `/` as the only separator, which takes a copy if `backslash`
+/// separates too and there is one.
+fn separated(bytes: &[u8], backslash: bool) -> Cow<'_, [u8]> {
+    match backslash && bytes.contains(&b'\\') {
+        true => bytes.iter().map(|&b| if b == b'\\' { b'/' } else { b }).collect(),
+        false => Cow::Borrowed(bytes),
+    }
+}
+
+/// Match all of `text` against the glob `pattern`.
+///
+/// On a mismatch, the last `*` takes one more byte of its component, or else
+/// the last `**` takes one more byte, which is linear in each backtrack point.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The positions after the last `*` and `**` in the pattern and the text.
+    let mut star: Option<(usize, usize)> = None;
+    let mut globstar: Option<(usize, usize)> = None;
+    loop {
+        match pattern.get(p) {
+            Some(b'*') if pattern.get(p + 1) == Some(&b'*') => {
+                p += 2;
+                globstar = Some((p, t));
+                star = None;
+                continue;
+            },
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            },
+            Some(b'?') if text.get(t).is_some_and(|c| *c != b'/') => {
+                p += 1;
+                t += 1;
+                continue;
+            },
+            Some(c) if text.get(t) == Some(c) => {
+                p += 1;
+                t += 1;
+                continue;
+            },
+            None if t == text.len() => return true,
+            _ => (),
+        }
+        match (star, globstar) {
+            (Some((sp, st)), _) if text.get(st).is_some_and(|c| *c != b'/') => {
+                star = Some((sp, st + 1));
+                (p, t) = (sp, st + 1);
+            },
+            (_, Some((gp, gt))) if gt < text.len() => {
+                star = None;
+                globstar = Some((gp, gt + 1));
+                (p, t) = (gp, gt + 1);
+            },
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separated_turns_backslashes_into_slashes() {
+        assert_eq!(&*separated(br"src\lib\a.c", true), b"src/lib/a.c");
+        assert!(matches!(separated(b"src/lib/a.c", true), Cow::Borrowed(_)));
+        assert_eq!(&*separated(br"src\lib\a.c", false), br"src\lib\a.c");
+    }
+
+    #[test]
+    fn patterns_match_native_separators() {
+        let glob = Pattern::compile("src/*.c");
+        assert!(glob.matches(Path::new("./src/a.c")));
+        assert_eq!(glob.matches(Path::new(r".\src\a.c")), BACKSLASH_SEPARATES);
+        let plain = Pattern::compile("/.git/");
+        assert!(plain.matches(Path::new("./.git/config")));
+        assert_eq!(plain.matches(Path::new(r".\.git\config")), BACKSLASH_SEPARATES);
+    }
+
+    #[test]
+    fn plain_patterns_match_substrings() {
+        let plain = Pattern::compile("/build/");
+        assert!(plain.matches(Path::new("./build/a.c")));
+        assert!(plain.matches(Path::new("./src/build/a.c")));
+        assert!(! plain.matches(Path::new("./builder/a.c")));
+    }
+
+    #[test]
+    fn globs_match_trailing_components() {
+        let glob = Pattern::compile("*.c");
+        assert!(glob.matches(Path::new("./src/a.c")));
+        assert!(! glob.matches(Path::new("./src/a.h")));
+        assert!(! glob.matches(Path::new("./a.c/b.h")));
+        let glob = Pattern::compile("rc/*.c");
+        assert!(! glob.matches(Path::new("./src/a.c")));
+        assert!(glob.matches(Path::new("./rc/a.c")));
+    }
+
+    #[test]
+    fn stars_stay_within_components() {
+        let star = Pattern::compile("src/*.c");
+        assert!(star.matches(Path::new("./src/a.c")));
+        assert!(! star.matches(Path::new("./src/lib/a.c")));
+        let globstar = Pattern::compile("src/**.c");
+        assert!(globstar.matches(Path::new("./src/lib/a.c")));
+        let mixed = Pattern::compile("**/x*y/z");
+        assert!(mixed.matches(Path::new("./a/xqy/z")));
+        assert!(! mixed.matches(Path::new("./a/xq/y/z")));
+        let question = Pattern::compile("a?b");
+        assert!(question.matches(Path::new("./axb")));
+        assert!(! question.matches(Path::new("./a/b")));
+    }
+
+    #[test]
+    fn globs_do_not_backtrack_exponentially() {
+        let text = format!("./{}", "a".repeat(200));
+        assert!(! Pattern::compile("*a*a*a*a*a*a*a*a*b").matches(Path::new(&text)));
+        assert!(! Pattern::compile("**a**a**a**a**a**a**a**a**b").matches(Path::new(&text)));
+        assert!(Pattern::compile("**a**a**a**a**a**a**a**a").matches(Path::new(&text)));
+    }
+
+    #[test]
+    fn patterns_reject_what_cannot_be_meant() {
+        assert!("".parse::<Pattern>().is_err());
+        assert!("src/***".parse::<Pattern>().is_err());
+        assert!("a\0b".parse::<Pattern>().is_err());
+        assert_eq!(Pattern::exclude("!gen/keep").unwrap(), (true, Pattern::compile("gen/keep")));
+        assert_eq!(Pattern::exclude("gen").unwrap(), (false, Pattern::compile("gen")));
+    }
+}
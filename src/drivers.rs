@@ -0,0 +1,894 @@
+//! MIME type drivers, the external tools that tell the type of a file.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{with_content, with_line, Console, Context, Priority, Reason, Reporter};
+
+/// Most distinct MIME types that are interned, later ones are allocated per file.
+const INTERNED: usize = 4096;
+
+/// The distinct MIME types the drivers reported, shared by all files of the type.
+static ESSENCES: RwLock<BTreeSet<Arc<str>>> = RwLock::new(BTreeSet::new());
+
+/// The shared copy of `essence`.
+fn intern(essence: &str) -> Arc<str> {
+    if let Some(interned) = ESSENCES.read().unwrap().get(essence) {
+        return Arc::clone(interned);
+    }
+    let mut essences = ESSENCES.write().unwrap();
+    if let Some(interned) = essences.get(essence) {
+        return Arc::clone(interned);
+    }
+    let interned: Arc<str> = Arc::from(essence);
+    if essences.len() < INTERNED {
+        essences.insert(Arc::clone(&interned));
+    }
+    interned
+}
+
+/// A MIME type like `text/x-csrc; charset=us-ascii`, as reported by a driver.
+///
+/// Type, subtype and parameter names are case-insensitive and kept in lowercase.
+/// Type and subtype are interned, so a clone does not allocate without parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MimeType {
+    essence: Arc<str>,
+    slash: usize,
+    params: Vec<(String, String)>,
+}
+
+impl MimeType {
+    /// The top-level type, like `text`.
+    pub fn main_type(&self) -> &str {
+        &self.essence[..self.slash]
+    }
+
+    /// The subtype, like `x-csrc`.
+    pub fn subtype(&self) -> &str {
+        &self.essence[self.slash + 1..]
+    }
+
+    /// Type and subtype without parameters, like `text/x-csrc`.
+    pub fn essence(&self) -> &str {
+        &self.essence
+    }
+
+    /// The value of the parameter `name`, like the `charset`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All parameters in the order given.
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Check if `pattern` names this type.
+    ///
+    /// A pattern with a slash is a full type, `*` matches any subtype as in
+    /// `text/*`. A pattern without one is a subtype of any top-level type,
+    /// with or without the unregistered `x-` prefix, so `rust` matches
+    /// both `text/rust` and `text/x-rust`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        match pattern.split_once('/') {
+            Some((main, "*")) => main.eq_ignore_ascii_case(self.main_type()),
+            Some(_) => pattern.eq_ignore_ascii_case(&self.essence),
+            None => {
+                let subtype = self.subtype();
+                subtype.eq_ignore_ascii_case(pattern)
+                    || subtype.strip_prefix("x-").is_some_and(|s| s.eq_ignore_ascii_case(pattern))
+            },
+        }
+    }
+}
+
+impl FromStr for MimeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(';');
+        let essence = parts.next().unwrap_or_default().trim();
+        let essence = match essence.contains(|c: char| c.is_uppercase()) {
+            true => Cow::Owned(essence.to_lowercase()),
+            false => Cow::Borrowed(essence),
+        };
+        let valid = |x: &str| ! x.is_empty() && ! x.contains(|c: char| c.is_whitespace() || c == '/');
+        let slash = match essence.split_once('/') {
+            Some((main, sub)) if valid(main) && valid(sub) => main.len(),
+            _ => return Err(format!("Invalid MIME type '{}'.", s.trim())),
+        };
+        let mut params = vec![];
+        for param in parts.map(str::trim).filter(|p| ! p.is_empty()) {
+            match param.split_once('=') {
+                Some((name, value)) if ! name.trim().is_empty() => params.push((
+                    name.trim().to_lowercase(),
+                    value.trim().trim_matches('"').to_string(),
+                )),
+                _ => return Err(format!("Invalid MIME type parameter '{}'.", param)),
+            }
+        }
+        Ok(MimeType { essence: intern(&essence), slash, params })
+    }
+}
+
+impl TryFrom<String> for MimeType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<MimeType> for String {
+    fn from(mime: MimeType) -> Self {
+        mime.to_string()
+    }
+}
+
+impl fmt::Display for MimeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.params.is_empty() {
+            return f.pad(&self.essence);
+        }
+        let mut s = self.essence.to_string();
+        for (name, value) in &self.params {
+            s.push_str(&format!("; {}={}", name, value));
+        }
+        f.pad(&s)
+    }
+}
+
+/// Generic driver abstraction.
+///
+/// Each mime-type driver needs to implement this trait.
+pub trait Driver
+{
+    fn name(&self) -> &str;
+    fn usable(&self) -> bool;
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>>;
+}
+
+/// A driver that uses the mimetype(1) tool for mime type checks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MimetypeDriver {
+    priority: Priority,
+}
+
+impl MimetypeDriver {
+    #[inline]
+    pub fn new(priority: Priority) -> Self {
+        MimetypeDriver { priority }
+    }
+
+    /// The command that prints the MIME type of `path`.
+    fn command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new("mimetype");
+        self.priority.apply(&mut cmd).args(["-b", "--mimetype"]).arg(path);
+        cmd
+    }
+}
+
+impl Driver for MimetypeDriver {
+    #[inline]
+    fn name(&self) -> &str {
+        "mimetype"
+    }
+
+    fn usable(&self) -> bool {
+        let mut cmd = Command::new("mimetype");
+        cmd.args(["-h"]);
+        cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn().is_ok()
+    }
+
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        let out = self.command(path).output()?;
+        let s = String::from_utf8(out.stdout)?;
+        Ok(s.parse()?)
+    }
+}
+
+/// A driver that uses the file(1) tool for mime type checks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FileDriver {
+    priority: Priority,
+}
+
+impl FileDriver {
+    #[inline]
+    pub fn new(priority: Priority) -> Self {
+        FileDriver { priority }
+    }
+
+    /// The command that prints the MIME type of `path`.
+    fn command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new("file");
+        self.priority.apply(&mut cmd).args(["-b", "--mime-type"]).arg(path);
+        cmd
+    }
+
+    /// The command that prints the MIME type of each path on stdin,
+    /// flushed after each line.
+    fn helper(&self) -> Command {
+        let mut cmd = Command::new("file");
+        self.priority.apply(&mut cmd).args(["-n", "-b", "--mime-type", "-f", "-"]);
+        cmd
+    }
+}
+
+impl Driver for FileDriver {
+    #[inline]
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn usable(&self) -> bool {
+        if let Ok(out) = Command::new("file").arg("-h").output() {
+            let s = String::from_utf8(out.stderr).unwrap_or_default();
+            if s.contains("--mime-type") {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        let out = self.command(path).output()?;
+        let s = String::from_utf8(out.stdout)?;
+        Ok(s.parse()?)
+    }
+}
+
+/// A driver that uses the xdg-mime(1) tool for mime type checks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct XdgMimeDriver {
+    priority: Priority,
+}
+
+impl XdgMimeDriver {
+    #[inline]
+    pub fn new(priority: Priority) -> Self {
+        XdgMimeDriver { priority }
+    }
+
+    /// The command that prints the MIME type of `path`.
+    fn command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new("xdg-mime");
+        self.priority.apply(&mut cmd).args(["query", "filetype"]).arg(path);
+        cmd
+    }
+}
+
+impl Driver for XdgMimeDriver {
+    #[inline]
+    fn name(&self) -> &str {
+        "xdg-mime"
+    }
+
+    fn usable(&self) -> bool {
+        let mut cmd = Command::new("xdg-mime");
+        cmd.args(["query", "filetype"]);
+        cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn().is_ok()
+    }
+
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        let out = self.command(path).output()?;
+        let s = String::from_utf8(out.stdout)?;
+        Ok(s.parse()?)
+    }
+}
+
+
+// A generic driver that abstracts all available drivers.
+//
+// This is the basis for a thread-safe approach to a List of Driver implementations.
+// Dynamic traits will not do this. So bite the bullet and add a new Enum value for each driver.
+// That also means to forward the interface accordingly.
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum GenericDriver {
+    XdgMimeDriver(XdgMimeDriver),
+    FileDriver(FileDriver),
+    MimetypeDriver(MimetypeDriver),
+    #[cfg(feature = "test-support")]
+    MockDriver(crate::test_support::MockDriver),
+}
+
+impl Driver for GenericDriver {
+    #[inline]
+    fn name(&self) -> &str {
+        match self {
+            GenericDriver::XdgMimeDriver(driver) => driver.name(),
+            GenericDriver::FileDriver(driver) => driver.name(),
+            GenericDriver::MimetypeDriver(driver) => driver.name(),
+            #[cfg(feature = "test-support")]
+            GenericDriver::MockDriver(driver) => driver.name(),
+        }
+    }
+
+    #[inline]
+    fn usable(&self) -> bool {
+        match self {
+            GenericDriver::XdgMimeDriver(driver) => driver.usable(),
+            GenericDriver::FileDriver(driver) => driver.usable(),
+            GenericDriver::MimetypeDriver(driver) => driver.usable(),
+            #[cfg(feature = "test-support")]
+            GenericDriver::MockDriver(driver) => driver.usable(),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err, skip_all,
+        fields(driver = self.name(), path = %path.display())))]
+    #[inline]
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        let mime = match self {
+            GenericDriver::XdgMimeDriver(driver) => driver.run(path),
+            GenericDriver::FileDriver(driver) => driver.run(path),
+            GenericDriver::MimetypeDriver(driver) => driver.run(path),
+            #[cfg(feature = "test-support")]
+            GenericDriver::MockDriver(driver) => driver.run(path),
+        };
+        mime.with_context(|| format!("Driver {}", self.name()))
+    }
+}
+
+impl GenericDriver {
+    /// The command of a long-lived process that prints the MIME type of
+    /// each path on its stdin, one per line, if the driver has one.
+    fn helper(&self) -> Option<Command> {
+        match self {
+            GenericDriver::FileDriver(driver) => Some(driver.helper()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl GenericDriver {
+    pub(crate) fn command(&self, path: &Path) -> Command {
+        match self {
+            GenericDriver::XdgMimeDriver(driver) => driver.command(path),
+            GenericDriver::FileDriver(driver) => driver.command(path),
+            GenericDriver::MimetypeDriver(driver) => driver.command(path),
+            #[cfg(feature = "test-support")]
+            GenericDriver::MockDriver(driver) => driver.command(path),
+        }
+    }
+}
+
+impl From<FileDriver> for GenericDriver {
+    #[inline]
+    fn from(driver: FileDriver) -> GenericDriver {
+        GenericDriver::FileDriver(driver)
+    }
+}
+
+impl From<XdgMimeDriver> for GenericDriver {
+    #[inline]
+    fn from(driver: XdgMimeDriver) -> GenericDriver {
+        GenericDriver::XdgMimeDriver(driver)
+    }
+}
+
+impl From<MimetypeDriver> for GenericDriver {
+    #[inline]
+    fn from(driver: MimetypeDriver) -> GenericDriver {
+        GenericDriver::MimetypeDriver(driver)
+    }
+}
+
+/// Output format for inspection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Fixed-width text, one line per file.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format '{}'.", s)),
+        }
+    }
+}
+
+/// Files below this size are sniffed in-process by default, see `DriverList::set_sniff_below()`.
+pub const SNIFF_SIZE: u64 = 4 * 1024;
+
+/// Interpreters of `#!` lines and the MIME types of their scripts.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "text/x-shellscript"),
+    ("bash", "text/x-shellscript"),
+    ("dash", "text/x-shellscript"),
+    ("ksh", "text/x-shellscript"),
+    ("zsh", "text/x-shellscript"),
+    ("python", "text/x-python"),
+    ("perl", "text/x-perl"),
+    ("ruby", "text/x-ruby"),
+    ("php", "text/x-php"),
+    ("lua", "text/x-lua"),
+    ("tclsh", "text/x-tcl"),
+];
+
+/// The MIME type of a file with `content`, if it is obvious without a driver:
+/// empty files, binaries with NUL bytes and scripts of known interpreters.
+///
+/// Other text is left to the drivers, which know far more formats.
+pub fn sniff(content: &[u8]) -> Option<MimeType> {
+    let mime = if content.is_empty() {
+        "inode/x-empty"
+    } else if content.contains(&0) {
+        "application/octet-stream"
+    } else {
+        let line = content.strip_prefix(b"#!")?.split(|&b| b == b'\n').next()?;
+        let line = std::str::from_utf8(line).ok()?;
+        let mut words = line.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            interpreter = words.find(|w| ! w.starts_with('-') && ! w.contains('='))?;
+        }
+        // Versioned names like python3 or perl5.36.
+        let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        INTERPRETERS.iter().find(|(name, _)| *name == interpreter)?.1
+    };
+    mime.parse().ok()
+}
+
+/// A long-lived driver process, asked about one path per line.
+#[derive(Debug)]
+struct Helper {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// The current request and answer, kept for their capacity.
+    request: Vec<u8>,
+    answer: String,
+}
+
+impl Helper {
+    fn spawn(mut command: Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("No pipes to the driver."));
+        };
+        Ok(Helper { child, stdin, stdout: BufReader::new(stdout), request: vec![], answer: String::new() })
+    }
+
+    /// The line the helper answers for `path`.
+    fn ask(&mut self, path: &Path) -> io::Result<&str> {
+        self.request.clear();
+        self.request.extend_from_slice(path.as_os_str().as_encoded_bytes());
+        self.request.push(b'\n');
+        self.stdin.write_all(&self.request)?;
+        self.answer.clear();
+        if self.stdout.read_line(&mut self.answer)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(&self.answer)
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        self.child.kill().unwrap_or_default();
+        self.child.wait().ok();
+    }
+}
+
+/// Long-lived driver processes shared by the jobs, so that each file does
+/// not cost a process, see `DriverList::set_helpers()`.
+#[derive(Debug)]
+pub(crate) struct HelperPool {
+    driver: GenericDriver,
+    /// Spawned on first use, and again after they failed.
+    helpers: Vec<Mutex<Option<Helper>>>,
+    next: AtomicUsize,
+}
+
+impl HelperPool {
+    /// The MIME type of `path` from an idle helper, or the next in turn.
+    ///
+    /// `None` if no helper can answer, then the driver runs on its own.
+    fn run(&self, path: &Path) -> Option<Result<MimeType, Box<dyn Error>>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let n = self.helpers.len();
+        let mut helper = (0..n)
+            .find_map(|i| self.helpers[(start + i) % n].try_lock().ok())
+            .unwrap_or_else(|| self.helpers[start % n].lock().unwrap());
+        if helper.is_none() {
+            *helper = Some(Helper::spawn(self.driver.helper()?).ok()?);
+        }
+        match helper.as_mut()?.ask(path) {
+            Ok(answer) => Some(answer.parse().map_err(Into::into)),
+            Err(_) => {
+                *helper = None; // Out of step or gone
+                None
+            },
+        }
+    }
+}
+
+/// The answer of one driver for a path, see `DriverList::detect_all()`.
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub driver: String,
+    /// The driver is the one the list uses.
+    pub selected: bool,
+    /// The MIME type or the error, `None` if the driver is not usable.
+    pub result: Option<Result<MimeType, String>>,
+    /// Time the driver took.
+    pub elapsed: Duration,
+}
+
+/// A collection of all available drivers.
+///
+/// The collection implements Driver itself and exposes the best
+/// candidate to the user.
+#[derive(Debug, Clone)]
+pub struct DriverList {
+    pub(crate) drivers: Vec<GenericDriver>,
+    pub(crate) current: GenericDriver,
+    pub(crate) inspect: bool,
+    pub(crate) format: Format,
+    pub(crate) print0: bool,
+    pub(crate) color: bool,
+    pub(crate) show: Option<bool>,
+    pub(crate) reporter: Arc<dyn Reporter>,
+    pub(crate) sniff_below: u64,
+    pub(crate) helpers: Option<Arc<HelperPool>>,
+}
+
+impl DriverList {
+    /// Create the list of drivers, with the `select`ed one or the first usable.
+    ///
+    /// Fails for an unknown `select`, suggesting the closest driver name.
+    pub fn new(select: Option<OsString>, inspect: bool, priority: Priority)
+        -> Result<Self, Box<dyn Error>>
+    {
+        let mut current: GenericDriver = XdgMimeDriver::new(priority).into();
+        // Push order determines preference.
+        let drivers = vec![
+            current.clone(),
+            FileDriver::new(priority).into(),
+            MimetypeDriver::new(priority).into(),
+        ];
+        for d in drivers.iter() {
+            match select {
+                None => {
+                    if d.usable() {
+                        current = d.clone();
+                        break;
+                    }
+                }
+                Some(ref name) => {
+                    if d.name() == name {
+                        current = d.clone();
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(ref name) = select {
+            if ! drivers.iter().any(|d| d.name() == name) {
+                return Err(unknown_driver(&name.to_string_lossy(), &drivers).into());
+            }
+        }
+
+        Ok(DriverList { drivers, current, inspect, format: Format::Text, print0: false, color: false,
+            show: None, reporter: Arc::new(Console), sniff_below: SNIFF_SIZE, helpers: None, })
+    }
+
+    /// Send the output of `inspect()` to `reporter` instead of the console.
+    pub fn set_reporter(&mut self, reporter: Arc<dyn Reporter>) {
+        self.reporter = reporter;
+    }
+
+    /// Terminate printed records with NUL instead of newline.
+    ///
+    /// This composes with `xargs -0`, even for paths with newlines.
+    pub fn set_print0(&mut self, print0: bool) {
+        self.print0 = print0;
+    }
+
+    /// Only print decisions that include (`Some(true)`) or exclude
+    /// (`Some(false)`) a file during inspection.
+    pub fn set_show(&mut self, show: Option<bool>) {
+        self.show = show;
+    }
+
+    /// Set the output format for inspection.
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Colorize the text output for inspection with ANSI escape sequences.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    /// Sniff regular files below `bytes` in-process instead of running the
+    /// driver, if their type is obvious, see `sniff()`. 0 turns it off.
+    ///
+    /// Spawning a driver costs far more than reading a small script.
+    pub fn set_sniff_below(&mut self, bytes: u64) {
+        self.sniff_below = bytes;
+    }
+
+    /// Ask up to `helpers` long-lived driver processes, shared by all jobs,
+    /// instead of running the driver for each file. 0 turns it off.
+    ///
+    /// Only `file` can serve several files, the other drivers still run per file.
+    pub fn set_helpers(&mut self, helpers: usize) {
+        self.helpers = (helpers > 0 && self.current.helper().is_some()).then(|| Arc::new(HelperPool {
+            driver: self.current.clone(),
+            helpers: (0..helpers).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+        }));
+    }
+
+    /// The MIME type of `path` if it is small enough to sniff and obvious.
+    pub fn sniff(&self, path: &Path) -> Option<MimeType> {
+        if self.sniff_below == 0 {
+            return None;
+        }
+        let file = fs::File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if ! metadata.is_file() || metadata.len() >= self.sniff_below {
+            return None;
+        }
+        with_content(|content| {
+            file.take(self.sniff_below).read_to_end(content).ok()?;
+            sniff(content)
+        })
+    }
+
+    /// The command of the current driver that prints the MIME type of `path`.
+    #[cfg(feature = "async")]
+    pub(crate) fn command(&self, path: &Path) -> Result<Command, Box<dyn Error + Send + Sync>> {
+        match self.usable() {
+            true => Ok(self.current.command(path)),
+            false => Err("No usable driver found.".into()),
+        }
+    }
+
+    /// All drivers in order of preference.
+    pub fn drivers(&self) -> impl Iterator<Item = &dyn Driver> {
+        self.drivers.iter().map(|d| d as &dyn Driver)
+    }
+
+    /// Ask every usable driver for the MIME type of `path`, in order of preference.
+    pub fn detect_all(&self, path: &Path) -> Vec<Detection> {
+        self.drivers.iter().map(|d| {
+            let usable = d.usable();
+            let start = Instant::now();
+            let result = usable.then(|| d.run(path).map_err(|e| e.to_string()));
+            Detection {
+                driver: d.name().to_string(),
+                selected: usable && d.name() == self.current.name(),
+                result,
+                elapsed: start.elapsed(),
+            }
+        }).collect()
+    }
+
+    pub fn inspect(&self,
+        reason: Reason,
+        path: &Path,
+        mime: Option<&MimeType>,
+        verbose: bool,
+    ) {
+        if self.inspect && self.show.is_some_and(|show| show != reason.include()) {
+            return;
+        }
+        let end = if self.print0 { '\0' } else { '\n' };
+        if self.inspect && self.format == Format::Json {
+            let record = serde_json::json!({
+                "path": path.to_string_lossy(),
+                "decision": if reason.include() { "include" } else { "exclude" },
+                "reason": reason.rule(),
+                "mime": mime,
+                "driver": self.current.name(),
+            });
+            with_line(format_args!("{}{}", record, end), |line| self.reporter.output(line));
+        } else if verbose {
+            with_line(format_args!("{}{}", path.display(), end), |line| self.reporter.output(line));
+        } else if self.inspect {
+            let mime: &dyn fmt::Display = match mime {
+                Some(mime) => mime,
+                None => &"",
+            };
+            if self.color {
+                const RESET: &str = "\x1b[0m";
+                let color = if reason.include() { "\x1b[32m" } else { "\x1b[31m" };
+                with_line(format_args!("{}{}{}: \x1b[36m{:29}{} {}{}",
+                    color, reason, RESET, mime, RESET, path.display(), end), |line| self.reporter.output(line));
+            } else {
+                with_line(format_args!("{}: {:29} {}{}", reason, mime, path.display(), end),
+                    |line| self.reporter.output(line));
+            }
+        }
+    }
+}
+
+impl Driver for DriverList {
+    #[inline]
+    fn name(&self) -> &str {
+        if self.usable() {
+            self.current.name()
+        } else {
+            "<none>"
+        }
+    }
+
+    #[inline]
+    fn usable(&self) -> bool {
+        self.current.usable()
+    }
+
+    /// Sniff small files, ask the helpers or the current driver about the others.
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        if let Some(mime) = self.sniff(path) {
+            return Ok(mime);
+        }
+        // A newline would split the request.
+        let helper = match self.helpers {
+            Some(ref helpers) if ! path.as_os_str().as_encoded_bytes().contains(&b'\n') => helpers.run(path),
+            _ => None,
+        };
+        if let Some(mime) = helper {
+            return mime.with_context(|| format!("Driver {}", self.current.name()));
+        }
+        if self.usable() {
+            self.current.run(path)
+        } else {
+            Err("No usable driver found.".into())
+        }
+    }
+}
+
+impl DriverList {
+    /// Describe all drivers as JSON, with the result of a probe run.
+    pub fn json(&self) -> serde_json::Value {
+        // Every driver can classify this, without reading any content.
+        const PROBE: &str = "/dev/null";
+        let drivers: Vec<_> = self.detect_all(Path::new(PROBE)).into_iter().map(|d| {
+            let probe = match d.result {
+                Some(Ok(ref mime)) => serde_json::json!({
+                    "mime": mime,
+                    "elapsed": d.elapsed.as_secs_f64(),
+                }),
+                Some(Err(ref e)) => serde_json::json!({ "error": e }),
+                None => serde_json::Value::Null,
+            };
+            serde_json::json!({
+                "name": d.driver,
+                "usable": d.result.is_some(),
+                "selected": d.selected,
+                "probe": probe,
+            })
+        }).collect();
+        serde_json::Value::from(drivers)
+    }
+}
+
+/// Error message for the unknown driver `name`.
+fn unknown_driver(name: &str, drivers: &[GenericDriver]) -> String {
+    let names: Vec<&str> = drivers.iter().map(|d| d.name()).collect();
+    let mut message = format!("Unknown driver '{}'.", name);
+    let closest = names.iter()
+        .map(|n| (strsim::levenshtein(name, n), n))
+        .min();
+    if let Some((distance, closest)) = closest {
+        if distance <= closest.len() / 2 {
+            message.push_str(&format!(" Did you mean '{}'?", closest));
+        }
+    }
+    message.push_str(&format!(" Available drivers: {}.", names.join(", ")));
+    message
+}
+
+impl fmt::Display for DriverList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, d) in self.drivers.iter().enumerate() {
+            write!(f, "[{}] {}", i, d.name())?;
+            if ! d.usable() {
+                write!(f, " (!)")?;
+            } else if d.name() == self.current.name() {
+                write!(f, " (*)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mime_types_parse_parameters() {
+        let mime: MimeType = r#" Text/X-CSrc; Charset="us-ascii" ; format=flowed "#.parse().unwrap();
+        assert_eq!(mime.essence(), "text/x-csrc");
+        assert_eq!((mime.main_type(), mime.subtype()), ("text", "x-csrc"));
+        assert_eq!(mime.param("CHARSET"), Some("us-ascii"));
+        assert_eq!(mime.params().collect::<Vec<_>>(), [("charset", "us-ascii"), ("format", "flowed")]);
+        assert_eq!(mime.to_string(), "text/x-csrc; charset=us-ascii; format=flowed");
+        assert_eq!(mime.to_string().parse::<MimeType>(), Ok(mime));
+    }
+
+    #[test]
+    fn mime_types_reject_malformed_input() {
+        for s in ["", "text", "text/", "/plain", "text/plain/x", "te xt/plain", "text/plain; charset"] {
+            assert!(s.parse::<MimeType>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn mime_types_match_patterns_in_any_case() {
+        let mime: MimeType = "text/x-rust".parse().unwrap();
+        for pattern in ["text/x-rust", "TEXT/X-Rust", "text/*", "Text/*", "rust", "x-rust", "RUST"] {
+            assert!(mime.matches(pattern), "{}", pattern);
+        }
+        for pattern in ["text/rust", "application/*", "x-csrc"] {
+            assert!(! mime.matches(pattern), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn sniff_recognizes_empty_and_binary_content() {
+        let sniffed = |content: &[u8]| sniff(content).map(|mime| mime.to_string());
+        assert_eq!(sniffed(b"").as_deref(), Some("inode/x-empty"));
+        assert_eq!(sniffed(b"\x7fELF\0\x02").as_deref(), Some("application/octet-stream"));
+        assert_eq!(sniffed(b"#!/bin/sh\n\0").as_deref(), Some("application/octet-stream"));
+        assert_eq!(sniffed(b"int main;\n"), None);
+    }
+
+    #[test]
+    fn sniff_recognizes_interpreters() {
+        let sniffed = |content: &[u8]| sniff(content).map(|mime| mime.to_string());
+        assert_eq!(sniffed(b"#!/bin/sh\necho").as_deref(), Some("text/x-shellscript"));
+        assert_eq!(sniffed(b"#! /usr/bin/python3 -u\n").as_deref(), Some("text/x-python"));
+        assert_eq!(sniffed(b"#!/usr/bin/env -S LC_ALL=C perl5.36 -w").as_deref(), Some("text/x-perl"));
+        assert_eq!(sniffed(b"#!/usr/bin/env\n"), None);
+        assert_eq!(sniffed(b"#!/usr/bin/awk -f\n"), None);
+        assert_eq!(sniffed(b"#!\n"), None);
+        assert_eq!(sniffed(b"# !/bin/sh\n"), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Errors that tell which file, driver, backend or phase failed.
+
+use std::error::Error;
+use std::fmt;
+
+/// An error with a description of what was being done, e.g.
+/// "Cannot write to cscope: Broken pipe (os error 32)".
+///
+/// The original error is the `source()`.
+#[derive(Debug)]
+pub struct ContextError {
+    context: String,
+    source: Box<dyn Error>,
+}
+
+impl ContextError {
+    /// What was being done.
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.source)
+    }
+}
+
+impl Error for ContextError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Attach context to the error of a `Result`.
+pub trait Context<T> {
+    /// Describe what failed with `context`.
+    fn context(self, context: impl fmt::Display) -> Result<T, Box<dyn Error>>;
+
+    /// Like `context()`, for descriptions that are only built on error.
+    fn with_context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T, E: Into<Box<dyn Error>>> Context<T> for Result<T, E> {
+    fn context(self, context: impl fmt::Display) -> Result<T, Box<dyn Error>> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T, Box<dyn Error>> {
+        self.map_err(|e| {
+            Box::new(ContextError { context: context().to_string(), source: e.into() }) as Box<dyn Error>
+        })
+    }
+}
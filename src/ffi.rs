@@ -0,0 +1,277 @@
+//! C API for embedding scope-rs, see `include/scope.h`.
+//!
+//! A `ScopeHandle` collects the settings, `scope_run()` creates the
+//! databases in the current directory like `Scope::run()`.
+//! Functions that can fail return 0 on success and -1 on error,
+//! `scope_last_error()` describes the error. `scope_cancel()` may be
+//! called from another thread while the handle is in use.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+#[cfg(unix)]
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{ScanSummary, Scope};
+
+/// Settings and results of one embedder.
+pub struct ScopeHandle {
+    /// Set by `scope_cancel()`, without waiting for the running call.
+    cancel: Arc<AtomicBool>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    roots: Vec<PathBuf>,
+    excludes: Vec<String>,
+    jobs: usize,
+    /// Built on demand, dropped when a setting changes.
+    scope: Option<Scope>,
+    summary: Option<ScanSummary>,
+    error: Option<CString>,
+}
+
+/// Counts of the last run.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScopeSummary {
+    pub crawled: usize,
+    pub included: usize,
+    pub excluded: usize,
+    pub errors: usize,
+    pub cancelled: bool,
+}
+
+impl From<&ScanSummary> for ScopeSummary {
+    fn from(summary: &ScanSummary) -> Self {
+        ScopeSummary {
+            crawled: summary.crawled,
+            included: summary.included(),
+            excluded: summary.excluded(),
+            errors: summary.errors,
+            cancelled: summary.cancelled,
+        }
+    }
+}
+
+impl Inner {
+    /// The scope for the current settings, stopped by `cancel`.
+    fn scope(&mut self, cancel: &Arc<AtomicBool>) -> Result<&Scope, String> {
+        if self.scope.is_none() {
+            let mut roots = self.roots.clone();
+            if roots.is_empty() {
+                roots.push(PathBuf::from("."));
+            }
+            let scope = Scope::builder()
+                .roots(roots)
+                .excludes(self.excludes.clone())
+                .jobs(self.jobs)
+                .cancel(Arc::clone(cancel))
+                .build()
+                .map_err(|e| e.to_string())?;
+            self.scope = Some(scope);
+        }
+        Ok(self.scope.as_ref().unwrap())
+    }
+}
+
+impl ScopeHandle {
+    /// Map the result of `f` to a return code and remember the error.
+    fn status(&self, f: impl FnOnce(&mut Inner) -> Result<c_int, String>) -> c_int {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut inner)))
+            .unwrap_or_else(|_| Err("Internal error.".to_string()));
+        match result {
+            Ok(status) => {
+                inner.error = None;
+                status
+            },
+            Err(e) => {
+                inner.error = CString::new(e).ok();
+                -1
+            },
+        }
+    }
+}
+
+/// Turn the C string `s` into a path.
+///
+/// # Safety
+///
+/// `s` is NULL or points to a NUL-terminated string.
+unsafe fn path<'a>(s: *const c_char) -> Result<&'a Path, String> {
+    if s.is_null() {
+        return Err("NULL path.".to_string());
+    }
+    let s = CStr::from_ptr(s);
+    #[cfg(unix)]
+    return Ok(Path::new(OsStr::from_bytes(s.to_bytes())));
+    #[cfg(not(unix))]
+    s.to_str().map(Path::new).map_err(|_| "Path is no UTF-8.".to_string())
+}
+
+/// Create a handle with the current directory as root and one job.
+///
+/// Release it with `scope_free()`.
+#[no_mangle]
+pub extern "C" fn scope_new() -> *mut ScopeHandle {
+    let inner = Inner {
+        roots: vec![],
+        excludes: vec![],
+        jobs: 1,
+        scope: None,
+        summary: None,
+        error: None,
+    };
+    let handle = ScopeHandle { cancel: Arc::new(AtomicBool::new(false)), inner: Mutex::new(inner) };
+    Box::into_raw(Box::new(handle))
+}
+
+/// Release a handle from `scope_new()`.
+///
+/// # Safety
+///
+/// `handle` is NULL or from `scope_new()` and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn scope_free(handle: *mut ScopeHandle) {
+    if ! handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Crawl `root`, instead of the current directory.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`, `root` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn scope_add_root(handle: *mut ScopeHandle, root: *const c_char) -> c_int {
+    let handle = &*handle;
+    handle.status(|h| {
+        h.roots.push(path(root)?.to_path_buf());
+        h.scope = None;
+        Ok(0)
+    })
+}
+
+/// Skip the paths that match `pattern`.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`, `pattern` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn scope_add_exclude(handle: *mut ScopeHandle, pattern: *const c_char) -> c_int {
+    let handle = &*handle;
+    handle.status(|h| {
+        let pattern = path(pattern)?.to_str().ok_or("Pattern is no UTF-8.")?;
+        h.excludes.push(pattern.to_string());
+        h.scope = None;
+        Ok(0)
+    })
+}
+
+/// Classify and index with `jobs` threads.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`.
+#[no_mangle]
+pub unsafe extern "C" fn scope_set_jobs(handle: *mut ScopeHandle, jobs: usize) -> c_int {
+    let handle = &*handle;
+    handle.status(|h| {
+        if jobs == 0 {
+            return Err("Scope needs at least one job.".to_string());
+        }
+        h.jobs = jobs;
+        h.scope = None;
+        Ok(0)
+    })
+}
+
+/// Create the databases in the current directory.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`.
+#[no_mangle]
+pub unsafe extern "C" fn scope_run(handle: *mut ScopeHandle) -> c_int {
+    let handle = &*handle;
+    let status = handle.status(|h| {
+        let summary = h.scope(&handle.cancel)?.run().map_err(|e| e.to_string())?;
+        h.summary = Some(summary);
+        Ok(0)
+    });
+    handle.cancel.store(false, Ordering::Relaxed);
+    status
+}
+
+/// Stop the running `scope_run()` on `handle` soon, or the next one.
+///
+/// The cancelled run returns 0 with a partial summary, and its incomplete
+/// databases are removed.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`, it may be in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn scope_cancel(handle: *const ScopeHandle) {
+    (*handle).cancel.store(true, Ordering::Relaxed);
+}
+
+/// Classify `file` without indexing it.
+///
+/// Returns 1 if it is a source file, 0 if not and -1 on error.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`, `file` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn scope_classify(handle: *mut ScopeHandle, file: *const c_char) -> c_int {
+    let handle = &*handle;
+    handle.status(|h| {
+        let file = path(file)?;
+        let scope = h.scope(&handle.cancel)?;
+        let classification = scope.classifier.classify(file, scope.driver.as_ref())
+            .map_err(|e| e.to_string())?;
+        Ok(c_int::from(classification.decision.include()))
+    })
+}
+
+/// Fill `summary` with the counts of the last `scope_run()`.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`, `summary` points to a `ScopeSummary`.
+#[no_mangle]
+pub unsafe extern "C" fn scope_summary(handle: *mut ScopeHandle, summary: *mut ScopeSummary) -> c_int {
+    let handle = &*handle;
+    handle.status(|h| {
+        let last = h.summary.as_ref().ok_or("Scope did not run yet.")?;
+        if summary.is_null() {
+            return Err("NULL summary.".to_string());
+        }
+        *summary = last.into();
+        Ok(0)
+    })
+}
+
+/// The error of the last failed call, or NULL.
+///
+/// The string belongs to the handle and is valid until the next call.
+///
+/// # Safety
+///
+/// `handle` is from `scope_new()`.
+#[no_mangle]
+pub unsafe extern "C" fn scope_last_error(handle: *const ScopeHandle) -> *const c_char {
+    let inner = (*handle).inner.lock().unwrap_or_else(|e| e.into_inner());
+    match inner.error {
+        Some(ref error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
@@ -0,0 +1,156 @@
+//! Keeping the databases of a `Scope` up to date after the first build,
+//! e.g. in an editor daemon.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{Backend, Consumer, FileCrawler, FileQueue, MimeType, ScanSummary, Schedule, Scope, ScopeBuilder};
+
+/// The databases of a `Scope` and the files in them.
+///
+/// ctags entries are updated per file, cscope is run again on the whole file
+/// list after each change and only parses the files that changed.
+#[derive(Debug)]
+pub struct Indexer {
+    scope: Scope,
+    /// The indexed files without a leading `./`, and as they were written
+    /// to the databases.
+    files: Arc<Mutex<BTreeMap<PathBuf, PathBuf>>>,
+    summary: ScanSummary,
+}
+
+impl Indexer {
+    /// Build the databases of the scope from `builder` and keep track of
+    /// their files.
+    ///
+    /// A consumer of the builder still gets each file of a full build.
+    pub fn new(builder: ScopeBuilder) -> Result<Self, Box<dyn Error>> {
+        let files = Arc::new(Mutex::new(BTreeMap::new()));
+        let consumer = {
+            let (files, consumer) = (Arc::clone(&files), builder.consumer.clone());
+            move |path: &Path, mime: Option<&MimeType>| {
+                files.lock().unwrap().insert(Indexer::key(path), path.to_path_buf());
+                if let Some(ref consumer) = consumer {
+                    consumer.consume(path, mime);
+                }
+            }
+        };
+        let scope = builder.consumer(Arc::new(consumer) as Arc<dyn Consumer>).build()?;
+        let mut indexer = Indexer { scope, files, summary: ScanSummary::default() };
+        indexer.rebuild()?;
+        Ok(indexer)
+    }
+
+    /// `path` for lookups, the same with and without a leading `./`.
+    fn key(path: &Path) -> PathBuf {
+        path.strip_prefix(".").unwrap_or(path).to_path_buf()
+    }
+
+    /// `path` as the crawler reports it, with `./` below the current directory.
+    fn crawled(path: &Path) -> PathBuf {
+        match path.is_relative() && ! path.starts_with(".") {
+            true => Path::new(".").join(path),
+            false => path.to_path_buf(),
+        }
+    }
+
+    /// The summary of the last full build.
+    pub fn summary(&self) -> &ScanSummary {
+        &self.summary
+    }
+
+    /// All indexed files.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Check if `path` is in the databases.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(&Indexer::key(path))
+    }
+
+    /// Index `path` if it is a source file and not excluded.
+    ///
+    /// Returns whether it is in the databases now.
+    pub fn add(&mut self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        if self.contains(path) {
+            return Ok(true);
+        }
+        if ! self.source(path)? {
+            return Ok(false);
+        }
+        self.insert(path);
+        self.update(path, true)?;
+        Ok(true)
+    }
+
+    /// Drop `path` from the databases.
+    ///
+    /// Returns whether it was in them.
+    pub fn remove(&mut self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        if self.files.lock().unwrap().remove(&Indexer::key(path)).is_none() {
+            return Ok(false);
+        }
+        self.update(path, false)?;
+        Ok(true)
+    }
+
+    /// Index `path` again after it changed, was created or deleted.
+    ///
+    /// It is classified again, so it may be added or removed.
+    /// Returns whether it is in the databases now.
+    pub fn refresh(&mut self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        if ! path.exists() || ! self.source(path)? {
+            self.remove(path)?;
+            return Ok(false);
+        }
+        self.insert(path);
+        self.update(path, true)?;
+        Ok(true)
+    }
+
+    /// Track `path`, written as the crawler would report it.
+    fn insert(&self, path: &Path) {
+        self.files.lock().unwrap().entry(Indexer::key(path)).or_insert_with(|| Indexer::crawled(path));
+    }
+
+    /// Crawl the roots again and create the databases from scratch.
+    pub fn rebuild(&mut self) -> Result<&ScanSummary, Box<dyn Error>> {
+        self.files.lock().unwrap().clear();
+        self.summary = self.scope.run()?;
+        Ok(&self.summary)
+    }
+
+    /// Check the excludes of the scope and classify `path`.
+    fn source(&self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        let crawler = FileCrawler::with_options(
+            vec![], self.scope.crawler.clone(), Arc::new(FileQueue::new(Schedule::Fifo, 0)));
+        if crawler.exclude_path(&Indexer::crawled(path)) {
+            return Ok(false);
+        }
+        let classification = self.scope.classifier.classify(path, self.scope.driver.as_ref())?;
+        Ok(classification.decision.include())
+    }
+
+    /// Bring the databases in line with `files` after `path` changed.
+    fn update(&self, path: &Path, include: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(ref cache) = self.scope.cache {
+            cache.invalidate(&Indexer::crawled(path));
+        }
+        let backends = &self.scope.options.backends;
+        let tag_files = self.scope.tag_files();
+        if backends.contains(&Backend::Ctags) {
+            tag_files.update_tags(path, include)?;
+        }
+        if backends.contains(&Backend::Cscope) {
+            let mut cscope = tag_files.backends(&[Backend::Cscope])
+                .spawn(self.scope.reporter.as_ref())?;
+            for file in self.files.lock().unwrap().values() {
+                cscope.writeln(file)?;
+            }
+        }
+        Ok(())
+    }
+}
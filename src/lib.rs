@@ -1,510 +1,1566 @@
 #![doc = include_str!("../README.md")]
 
-use std::collections::VecDeque;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod classify;
+pub mod crawl;
+pub mod drivers;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod indexer;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod tags;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+pub use classify::{
+    Action,
+    Classification,
+    ClassifierConfig,
+    ContentPattern,
+    Decision,
+    Exclusion,
+    FileClassifier,
+    HEURISTICS,
+    Heuristic,
+    LANGUAGES,
+    Language,
+    Reason,
+    Rule,
+};
+pub use crawl::{
+    CrawlerOptions,
+    DEFAULT_EXCLUDES,
+    FileCrawler,
+    FileQueue,
+    MaxSize,
+    MetadataCache,
+    ModifiedSince,
+    Owner,
+    PathFilter,
+    PathStyle,
+    Pattern,
+    QueueWorker,
+    Schedule,
+};
+pub use drivers::{Detection, Driver, DriverList, Format, MimeType, SNIFF_SIZE};
+pub use error::{Context, ContextError};
+pub use indexer::Indexer;
+pub use tags::{Backend, IndexerOptions, TagFileCreator, TagFileCreatorBuilder};
+
+/// The types most embedders need, for `use scope_rs::prelude::*`.
+pub mod prelude {
+    pub use crate::{
+        Backend,
+        Classification,
+        ClassifierConfig,
+        Consumer,
+        CrawlerOptions,
+        Decision,
+        Driver,
+        DriverList,
+        Event,
+        FileClassifier,
+        Hooks,
+        Indexer,
+        IndexerOptions,
+        MimeType,
+        PathFilter,
+        Phase,
+        Priority,
+        Reason,
+        Reporter,
+        ScanSummary,
+        Schedule,
+        Scope,
+        ScopeBuilder,
+        WorkerSummary,
+    };
+}
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::ffi::OsString;
 use std::fmt;
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command, Stdio};
-use std::sync::{Arc, Mutex};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
 
-/// Generic driver abstraction.
+/// Number of source files buffered between the classify and write stages.
+pub(crate) const WRITE_BUFFER: usize = 1024;
+
+/// Most source files written to the indexers at once.
+pub(crate) const WRITE_BATCH: usize = 64;
+
+/// How long a worker waits for the crawler when the queue is empty.
+pub(crate) const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+thread_local! {
+    /// File content read on this thread, kept for its capacity.
+    static CONTENT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// A line formatted on this thread, kept for its capacity.
+    static LINE: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Call `f` with the empty content buffer of this thread.
 ///
-/// Each mime-type driver needs to implement this trait.
-pub trait Driver
-{
-    fn name(&self) -> &str;
-    fn usable(&self) -> bool;
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>>;
+/// Workers reuse it for each file instead of allocating. `f` must not
+/// call this again.
+pub(crate) fn with_content<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    CONTENT.with_borrow_mut(|content| {
+        content.clear();
+        f(content)
+    })
 }
 
-/// A driver that uses the mimetype(1) tool for mime type checks.
-#[derive(Debug, Clone, Copy)]
-struct MimetypeDriver {}
+/// Format `args` in the line buffer of this thread and pass the line to `f`.
+pub(crate) fn with_line<R>(args: fmt::Arguments, f: impl FnOnce(&str) -> R) -> R {
+    LINE.with_borrow_mut(|line| {
+        line.clear();
+        fmt::Write::write_fmt(line, args).expect("Formatting into a String does not fail.");
+        f(line)
+    })
+}
 
-impl MimetypeDriver {
-    #[inline]
-    pub fn new() -> Self {
-        MimetypeDriver {}
-    }
+/// I/O scheduling class, see ionice(1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
 }
 
-impl Driver for MimetypeDriver {
-    #[inline]
-    fn name(&self) -> &str {
-        "mimetype"
-    }
+/// I/O scheduling class and level for spawned processes.
+///
+/// Parses from `idle`, `best-effort[:level]` or `realtime[:level]`.
+/// The level ranges from 0 (highest) to 7 (lowest).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IoNice {
+    pub class: IoClass,
+    pub level: u8,
+}
 
-    fn usable(&self) -> bool {
-        let mut cmd = Command::new("mimetype");
-        cmd.args(["-h"]);
-        cmd
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn().is_ok()
+impl FromStr for IoNice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+        let class = match class {
+            "realtime" | "rt" => IoClass::Realtime,
+            "best-effort" | "be" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            _ => return Err(format!("Unknown I/O class '{}'.", class)),
+        };
+        let level = match level {
+            Some(level) => match level.parse::<u8>() {
+                Ok(level) if level <= 7 => level,
+                _ => return Err(format!("Invalid I/O level '{}'.", level)),
+            },
+            None => 4,
+        };
+        Ok(IoNice { class, level })
     }
+}
 
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        let mut cmd = Command::new("mimetype");
-        cmd.args(["-b", "--mimetype"]);
-        let out = cmd.arg(path).output()?;
-        let s = String::from_utf8(out.stdout)?;
-        Ok(s.trim().into())
+impl TryFrom<String> for IoNice {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
     }
 }
 
-/// A driver that uses the file(1) tool for mime type checks.
-#[derive(Debug, Clone, Copy)]
-struct FileDriver {}
+impl From<IoNice> for String {
+    fn from(ionice: IoNice) -> Self {
+        ionice.to_string()
+    }
+}
 
-impl FileDriver {
-    #[inline]
-    pub fn new() -> Self {
-        FileDriver {}
+impl fmt::Display for IoNice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.class {
+            IoClass::Realtime => write!(f, "realtime:{}", self.level),
+            IoClass::BestEffort => write!(f, "best-effort:{}", self.level),
+            IoClass::Idle => write!(f, "idle"),
+        }
     }
 }
 
-impl Driver for FileDriver {
-    #[inline]
-    fn name(&self) -> &str {
-        "file"
+/// Scheduling priority for spawned processes.
+///
+/// Applies to cscope, ctags and the mime type drivers,
+/// so that background reindexing does not degrade interactive work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Priority {
+    pub nice: Option<i32>,
+    pub ionice: Option<IoNice>,
+}
+
+impl Priority {
+    /// Set up `cmd` to run with this priority.
+    pub fn apply<'a>(&self, cmd: &'a mut Command) -> &'a mut Command {
+        #[cfg(unix)]
+        if *self != Priority::default() {
+            use std::os::unix::process::CommandExt;
+            let priority = *self;
+            // SAFETY: The closure only issues async-signal-safe system calls.
+            unsafe {
+                cmd.pre_exec(move || priority.set());
+            }
+        }
+        cmd
     }
 
-    fn usable(&self) -> bool {
-        if let Ok(out) = Command::new("file").arg("-h").output() {
-            let s = String::from_utf8(out.stderr).unwrap_or_default();
-            if s.contains("--mime-type") {
-                return true;
+    /// Set the priority of the calling process.
+    #[cfg(unix)]
+    fn set(&self) -> std::io::Result<()> {
+        if let Some(nice) = self.nice {
+            // SAFETY: Plain system call without pointer arguments.
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) } != 0 {
+                return Err(std::io::Error::last_os_error());
             }
         }
-        false
+        #[cfg(target_os = "linux")]
+        if let Some(ionice) = self.ionice {
+            const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+            const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+            let class: libc::c_long = match ionice.class {
+                IoClass::Realtime => 1,
+                IoClass::BestEffort => 2,
+                IoClass::Idle => 3,
+            };
+            let ioprio = class << IOPRIO_CLASS_SHIFT | libc::c_long::from(ionice.level);
+            // SAFETY: Plain system call without pointer arguments.
+            if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
     }
+}
 
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        let mut cmd = Command::new("file");
-        cmd.args(["-b", "--mime-type"]);
-        let out = cmd.arg(path).output()?;
-        let s = String::from_utf8(out.stdout)?;
-        Ok(s.trim().into())
+/// Receives the messages of the library, instead of stdout and stderr.
+///
+/// Embedders implement this to route the output into their own UI or log.
+pub trait Reporter: Send + Sync {
+    /// Print a formatted `record`, e.g. the decision for a file,
+    /// including its terminator.
+    fn output(&self, record: &str);
+
+    /// Report a problem that does not stop the run.
+    fn warning(&self, message: &str);
+}
+
+impl fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Reporter")
     }
 }
 
-/// A driver that uses the xdg-mime(1) tool for mime type checks.
-#[derive(Debug, Clone, Copy)]
-struct XdgMimeDriver {}
+/// Receives each file to index, in addition to or instead of the indexers.
+///
+/// Tools that only need the source files set no backends and
+/// get them without cscope or ctags, see `ScopeBuilder::consumer()`.
+pub trait Consumer: Send + Sync {
+    /// `path` is a source file, with its MIME type if the driver was asked.
+    fn consume(&self, path: &Path, mime: Option<&MimeType>);
+}
 
-impl XdgMimeDriver {
-    #[inline]
-    pub fn new() -> Self {
-        XdgMimeDriver {}
+impl<F: Fn(&Path, Option<&MimeType>) + Send + Sync> Consumer for F {
+    fn consume(&self, path: &Path, mime: Option<&MimeType>) {
+        self(path, mime)
     }
 }
 
-impl Driver for XdgMimeDriver {
-    #[inline]
-    fn name(&self) -> &str {
-        "xdg-mime"
+impl fmt::Debug for dyn Consumer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Consumer")
     }
+}
 
-    fn usable(&self) -> bool {
-        let mut cmd = Command::new("xdg-mime");
-        cmd.args(["query", "filetype"]);
-        cmd
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn().is_ok()
+/// Hooks into the stages of a run, for embedders that keep records across
+/// runs, steer the workers or show what each of them does,
+/// see `ScopeBuilder::hooks()`.
+///
+/// Every method does nothing by default. Only `run()` calls the worker
+/// methods `worker()`, `admit()`, `working()` and `send()`.
+pub trait Hooks: Send + Sync {
+    /// The run enters `phase`.
+    fn phase(&self, _phase: Phase) {}
+
+    /// Worker `id` starts, on its own thread.
+    fn worker(&self, _id: usize) {}
+
+    /// Whether worker `id` takes another file.
+    ///
+    /// It may block to park the worker, `done` tells once no files are left.
+    fn admit(&self, _id: usize, _done: &dyn Fn() -> bool) -> bool {
+        true
+    }
+
+    /// Worker `id` works on `path` now, or waits for the crawler with `None`.
+    fn working(&self, _id: usize, _path: Option<&Path>) {}
+
+    /// The decision of an earlier run for `path`, to take instead of classifying it.
+    fn recall(&self, _path: &Path) -> Option<Recall> {
+        None
+    }
+
+    /// The source file that `path` has the same content as, if it came first.
+    ///
+    /// `path` is then not indexed.
+    fn original(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Hand a source file to the writer with `send`, which blocks while
+    /// the indexers are behind, and return its result.
+    fn send(&self, send: &mut dyn FnMut() -> bool) -> bool {
+        send()
     }
 
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        let mut cmd = Command::new("xdg-mime");
-        cmd.args(["query", "filetype"]);
-        let out = cmd.arg(path).output()?;
-        let s = String::from_utf8(out.stdout)?;
-        Ok(s.trim().into())
+    /// A file is decided, see `Decided`.
+    fn decided(&self, _decided: &Decided) {}
+}
+
+impl fmt::Debug for dyn Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Hooks")
     }
 }
 
+/// A decision of an earlier run, see `Hooks::recall()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recall {
+    /// The language of a source file, `None` for an excluded one.
+    pub language: Option<String>,
+    /// The file did not change since the last complete run. Otherwise an
+    /// interrupted run decided it.
+    pub unchanged: bool,
+}
 
-// A generic driver that abstracts all available drivers.
-//
-// This is the basis for a thread-safe approach to a List of Driver implementations.
-// Dynamic traits will not do this. So bite the bullet and add a new Enum value for each driver.
-// That also means to forward the interface accordingly.
+/// The outcome for one file, see `Hooks::decided()`.
 #[derive(Debug, Clone, Copy)]
-enum GenericDriver {
-    XdgMimeDriver(XdgMimeDriver),
-    FileDriver(FileDriver),
-    MimetypeDriver(MimetypeDriver),
+pub struct Decided<'a> {
+    /// The path as crawled.
+    pub path: &'a Path,
+    /// The path as written to the databases, see `ScopeBuilder::path_style()`.
+    pub shown: &'a Path,
+    /// What decided: `extension`, `mime` or `rule` after classifying,
+    /// `resume` or `unchanged` for a recalled file, `duplicate` or `error`.
+    pub rule: &'static str,
+    /// The reason of a classified file.
+    pub reason: Option<Reason>,
+    pub mime: Option<&'a MimeType>,
+    /// The language of a source file, also of a duplicate.
+    pub language: Option<&'a str>,
+    /// Whether the indexers took a source file, or their error.
+    /// `None` if the file was not written to any.
+    pub index: Option<Result<(), &'a str>>,
 }
 
-impl Driver for GenericDriver {
-    #[inline]
-    fn name(&self) -> &str {
-        match self {
-            GenericDriver::XdgMimeDriver(driver) => driver.name(),
-            GenericDriver::FileDriver(driver) => driver.name(),
-            GenericDriver::MimetypeDriver(driver) => driver.name(),
+/// Reporter for the console, records go to stdout and warnings to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Console;
+
+impl Reporter for Console {
+    fn output(&self, record: &str) {
+        print!("{}", record);
+    }
+
+    fn warning(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Utilization of one worker of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerSummary {
+    /// Paths taken from the crawler.
+    pub files: usize,
+    /// Time spent classifying and indexing.
+    pub busy: Duration,
+    /// Time spent waiting for the crawler.
+    pub idle: Duration,
+}
+
+impl WorkerSummary {
+    /// Add the `idle` time between `start` and now.
+    pub(crate) fn wait(&mut self, start: Instant) {
+        self.idle += start.elapsed();
+    }
+
+    /// The share of time the worker was busy, between 0 and 1.
+    pub fn utilization(&self) -> f64 {
+        let total = (self.busy + self.idle).as_secs_f64();
+        match total > 0.0 {
+            true => self.busy.as_secs_f64() / total,
+            false => 0.0,
         }
     }
+}
+
+/// Outcome of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanSummary {
+    /// Paths taken from the crawler, including directories.
+    pub crawled: usize,
+    /// Files decided by a previous run.
+    pub resumed: usize,
+    /// Files decided by a previous build, as they did not change since.
+    pub unchanged: usize,
+    pub by_extension: usize,
+    pub by_mime: usize,
+    pub by_rule: usize,
+    pub excluded_pattern: usize,
+    pub excluded_mime: usize,
+    pub excluded_rule: usize,
+    /// Files with an extension that is never source.
+    pub excluded_binary: usize,
+    /// Source files that were not indexed, as an earlier one has the same content.
+    ///
+    /// They are not counted as included by extension, MIME type or rule.
+    pub duplicates: usize,
+    pub errors: usize,
+    /// Source files the indexers did not take, also counted in `errors`.
+    pub backend_errors: usize,
+    /// Files the driver was asked about.
+    pub driver_calls: usize,
+    /// The run was cancelled, the counts are partial.
+    pub cancelled: bool,
+    /// The run was drained, the crawl is incomplete but the databases
+    /// have all files found before.
+    pub drained: bool,
+    /// Excluded files per rule and detail, like the MIME type or the pattern.
+    pub exclusions: BTreeMap<(&'static str, String), usize>,
+    /// Utilization of each worker, see `ScopeBuilder::jobs()`.
+    pub workers: Vec<WorkerSummary>,
+    /// cscope updated its existing database instead of building it anew,
+    /// `None` without cscope.
+    pub cscope_incremental: Option<bool>,
+    pub crawl_time: Duration,
+    pub classify_time: Duration,
+    pub finalize_time: Duration,
+    /// Time in the driver, summed over the workers.
+    pub driver_time: Duration,
+    /// Time classifying besides the driver, summed over the workers.
+    pub rules_time: Duration,
+    /// Time writing to the indexers.
+    pub write_time: Duration,
+}
 
-    #[inline]
-    fn usable(&self) -> bool {
-        match self {
-            GenericDriver::XdgMimeDriver(driver) => driver.usable(),
-            GenericDriver::FileDriver(driver) => driver.usable(),
-            GenericDriver::MimetypeDriver(driver) => driver.usable(),
+impl ScanSummary {
+    /// Number of files indexed.
+    pub fn included(&self) -> usize {
+        self.by_extension + self.by_mime + self.by_rule
+    }
+
+    /// Number of paths skipped.
+    pub fn excluded(&self) -> usize {
+        self.excluded_pattern + self.excluded_mime + self.excluded_rule + self.excluded_binary
+    }
+
+    /// Count `n` files excluded by `rule`, for the `detail` like the MIME type.
+    pub fn exclude(&mut self, rule: &'static str, detail: &str, n: usize) {
+        *self.exclusions.entry((rule, detail.to_string())).or_default() += n;
+    }
+
+    /// Count a file included by `decision`.
+    pub(crate) fn include(&mut self, decision: &Decision) {
+        match decision {
+            Decision::IncludedByExtension => self.by_extension += 1,
+            Decision::IncludedByRule(_) => self.by_rule += 1,
+            _ => self.by_mime += 1,
         }
     }
 
-    #[inline]
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        match self {
-            GenericDriver::XdgMimeDriver(driver) => driver.run(path),
-            GenericDriver::FileDriver(driver) => driver.run(path),
-            GenericDriver::MimetypeDriver(driver) => driver.run(path),
+    /// Count `path` excluded for `exclusion`, with a detail from `rules`.
+    pub(crate) fn exclude_by(&mut self, path: &Path, exclusion: &Exclusion, rules: &[Rule]) {
+        match *exclusion {
+            Exclusion::Rule(i) => {
+                self.excluded_rule += 1;
+                let rule = rules.get(i).map(Rule::to_string).unwrap_or_default();
+                with_line(format_args!("#{} {}", i + 1, rule), |detail| self.exclude("rule", detail, 1));
+            },
+            Exclusion::Mime(ref mime) => {
+                self.excluded_mime += 1;
+                self.exclude("mime", mime.essence(), 1);
+            },
+            Exclusion::Binary => {
+                self.excluded_binary += 1;
+                let ext = path.extension().unwrap_or_default().to_string_lossy();
+                self.exclude("extension", &ext.to_lowercase(), 1);
+            },
         }
     }
-}
 
-impl From<FileDriver> for GenericDriver {
-    #[inline]
-    fn from(driver: FileDriver) -> GenericDriver {
-        GenericDriver::FileDriver(driver)
+    /// Add the counts and times of `other`, e.g. of the next set of databases.
+    ///
+    /// Workers are added up by their index.
+    pub fn add(&mut self, other: &ScanSummary) {
+        self.crawled += other.crawled;
+        self.resumed += other.resumed;
+        self.unchanged += other.unchanged;
+        self.by_extension += other.by_extension;
+        self.by_mime += other.by_mime;
+        self.by_rule += other.by_rule;
+        self.excluded_pattern += other.excluded_pattern;
+        self.excluded_mime += other.excluded_mime;
+        self.excluded_rule += other.excluded_rule;
+        self.excluded_binary += other.excluded_binary;
+        self.duplicates += other.duplicates;
+        self.errors += other.errors;
+        self.backend_errors += other.backend_errors;
+        self.driver_calls += other.driver_calls;
+        self.cancelled |= other.cancelled;
+        self.drained |= other.drained;
+        for ((rule, detail), n) in &other.exclusions {
+            self.exclude(rule, detail, *n);
+        }
+        if self.workers.len() < other.workers.len() {
+            self.workers.resize(other.workers.len(), WorkerSummary::default());
+        }
+        for (total, worker) in self.workers.iter_mut().zip(&other.workers) {
+            total.files += worker.files;
+            total.busy += worker.busy;
+            total.idle += worker.idle;
+        }
+        self.cscope_incremental = other.cscope_incremental.or(self.cscope_incremental);
+        self.crawl_time += other.crawl_time;
+        self.classify_time += other.classify_time;
+        self.finalize_time += other.finalize_time;
+        self.driver_time += other.driver_time;
+        self.rules_time += other.rules_time;
+        self.write_time += other.write_time;
+    }
+
+    /// The share of time all workers were busy, between 0 and 1.
+    ///
+    /// Workers that are mostly idle wait for the crawler, fewer jobs do as well.
+    pub fn utilization(&self) -> f64 {
+        let busy: Duration = self.workers.iter().map(|w| w.busy).sum();
+        let idle: Duration = self.workers.iter().map(|w| w.idle).sum();
+        WorkerSummary { files: 0, busy, idle }.utilization()
+    }
+
+    /// Table of files, busy and idle time per worker.
+    pub fn worker_report(&self) -> String {
+        let mut result = format!("{:8} {:>10} {:>10} {:>10} {:>6}\n",
+            "Worker", "Files", "Busy", "Idle", "Util");
+        for (id, worker) in self.workers.iter().enumerate() {
+            result.push_str(&format!("{:<8} {:>10} {:>9.3}s {:>9.3}s {:>5.0}%\n", id,
+                worker.files, worker.busy.as_secs_f64(), worker.idle.as_secs_f64(),
+                worker.utilization() * 100.0));
+        }
+        result
+    }
+
+    /// Table of the time spent in each phase, to find the bottleneck.
+    ///
+    /// Drivers and rules are summed over the workers, so they may exceed the
+    /// time of the run.
+    pub fn timings(&self) -> String {
+        let mut result = format!("{:12} {:>10}\n", "Phase", "Seconds");
+        for (phase, time) in [
+            ("Crawling", self.crawl_time),
+            ("Drivers", self.driver_time),
+            ("Rules", self.rules_time),
+            ("Writing", self.write_time),
+            ("Finalizing", self.finalize_time),
+        ] {
+            result.push_str(&format!("{:12} {:>10.3}\n", phase, time.as_secs_f64()));
+        }
+        result
+    }
+
+    /// Table of excluded files per rule and detail, most frequent first.
+    pub fn report(&self) -> String {
+        let mut sorted: Vec<_> = self.exclusions.iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let mut result = format!("{:10} {:>10}  {}\n", "Excluded", "Files", "Detail");
+        for ((rule, detail), n) in sorted {
+            result.push_str(&format!("{:10} {:>10}  {}\n", rule, n, detail));
+        }
+        result
     }
 }
 
-impl From<XdgMimeDriver> for GenericDriver {
-    #[inline]
-    fn from(driver: XdgMimeDriver) -> GenericDriver {
-        GenericDriver::XdgMimeDriver(driver)
+impl fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Crawled:            {:>10}", self.crawled)?;
+        writeln!(f, "Resumed:            {:>10}", self.resumed)?;
+        writeln!(f, "Unchanged:          {:>10}", self.unchanged)?;
+        writeln!(f, "Included [.ext]:    {:>10}", self.by_extension)?;
+        writeln!(f, "Included [mime]:    {:>10}", self.by_mime)?;
+        writeln!(f, "Included [rule]:    {:>10}", self.by_rule)?;
+        writeln!(f, "Excluded [pattern]: {:>10}", self.excluded_pattern)?;
+        writeln!(f, "Excluded [mime]:    {:>10}", self.excluded_mime)?;
+        writeln!(f, "Excluded [rule]:    {:>10}", self.excluded_rule)?;
+        writeln!(f, "Excluded [.bin]:    {:>10}", self.excluded_binary)?;
+        if self.duplicates > 0 {
+            writeln!(f, "Duplicates:         {:>10}", self.duplicates)?;
+        }
+        writeln!(f, "Errors:             {:>10}", self.errors)?;
+        if let Some(incremental) = self.cscope_incremental {
+            writeln!(f, "Cscope database:    {:>10}", if incremental { "updated" } else { "rebuilt" })?;
+        }
+        if ! self.workers.is_empty() {
+            writeln!(f, "Utilization:        {:>10.0}%", self.utilization() * 100.0)?;
+        }
+        writeln!(f, "Crawling:           {:>10.3}s", self.crawl_time.as_secs_f64())?;
+        writeln!(f, "Classifying:        {:>10.3}s", self.classify_time.as_secs_f64())?;
+        write!(f, "Finalizing:         {:>10.3}s", self.finalize_time.as_secs_f64())
     }
 }
 
-impl From<MimetypeDriver> for GenericDriver {
-    #[inline]
-    fn from(driver: MimetypeDriver) -> GenericDriver {
-        GenericDriver::MimetypeDriver(driver)
+/// Phase of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Crawling, classifying and indexing, all at once.
+    Crawl,
+    /// Waiting for the indexers to write the databases.
+    Finalize,
+}
+
+/// Something that happened during a run, see `Scope::events()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The run enters a new phase.
+    PhaseChanged(Phase),
+    /// `path` goes into the databases because of `rule`.
+    FileIncluded { path: PathBuf, mime: Option<MimeType>, rule: Reason },
+    /// `path` is no source file.
+    FileExcluded { path: PathBuf, mime: Option<MimeType>, reason: Reason },
+    /// `path` was decided by an earlier run, with the `language` of a source file.
+    FileRecalled { path: PathBuf, language: Option<String> },
+    /// `path` is not indexed, as `original` has the same content.
+    FileDuplicate { path: PathBuf, original: PathBuf },
+    /// `path` could not be classified.
+    FileError { path: PathBuf, message: String },
+    /// `path` could not be passed to the indexers.
+    BackendError { path: PathBuf, message: String },
+    /// The run is over.
+    Done(ScanSummary),
+    /// The run could not complete.
+    Failed(String),
+}
+
+/// Builder for a `Scope`, see `Scope::builder()`.
+pub struct ScopeBuilder {
+    roots: Vec<PathBuf>,
+    files: Option<Vec<PathBuf>>,
+    crawler: CrawlerOptions,
+    jobs: usize,
+    driver: Option<OsString>,
+    /// Drivers to use instead of the ones of the system.
+    drivers: Option<DriverList>,
+    languages: Option<Vec<String>>,
+    classifier: ClassifierConfig,
+    priority: Priority,
+    options: IndexerOptions,
+    name: Option<String>,
+    reporter: Arc<dyn Reporter>,
+    consumer: Option<Arc<dyn Consumer>>,
+    hooks: Option<Arc<dyn Hooks>>,
+    style: PathStyle,
+    cancel: Arc<AtomicBool>,
+    drain: Arc<AtomicBool>,
+    filters: Vec<Arc<dyn PathFilter>>,
+    cache: Option<Arc<MetadataCache>>,
+    sniff_below: Option<u64>,
+    helpers: Option<usize>,
+    keep_partial: bool,
+}
+
+impl ScopeBuilder {
+    /// Directories to crawl, the current directory by default.
+    pub fn roots<P: Into<PathBuf>>(mut self, roots: impl IntoIterator<Item = P>) -> Self {
+        self.roots = roots.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Classify and index exactly these files instead of crawling the roots,
+    /// e.g. the sources from a build graph.
+    ///
+    /// The excludes and filters of the crawler do not apply to them.
+    pub fn files<P: Into<PathBuf>>(mut self, files: impl IntoIterator<Item = P>) -> Self {
+        self.files = Some(files.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Patterns to exclude, in addition to `DEFAULT_EXCLUDES`.
+    pub fn excludes<S: Into<String>>(mut self, excludes: impl IntoIterator<Item = S>) -> Self {
+        self.crawler.excludes = excludes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Number of threads to classify files, one by default.
+    ///
+    /// One more thread writes the source files to the indexers.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Number of threads to crawl the directories, one by default.
+    pub fn crawl_threads(mut self, threads: usize) -> Self {
+        self.crawler.threads = threads;
+        self
+    }
+
+    /// Order in which the files are indexed, FIFO by default.
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.crawler.schedule = schedule;
+        self
+    }
+
+    /// Keep at most `files` queued files in memory and spill the others
+    /// to a temporary file, unlimited by default.
+    pub fn queue_limit(mut self, files: usize) -> Self {
+        self.crawler.queue_limit = Some(files);
+        self
+    }
+
+    /// All options of the crawler at once, the excludes are still
+    /// in addition to `DEFAULT_EXCLUDES`.
+    pub fn crawler_options(mut self, options: CrawlerOptions) -> Self {
+        self.crawler = options;
+        self
+    }
+
+    /// All options of the indexers at once.
+    pub fn indexer_options(mut self, options: IndexerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The databases to create, cscope and ctags by default.
+    pub fn backends(mut self, backends: &[Backend]) -> Self {
+        self.options.backends = backends.to_vec();
+        self
+    }
+
+    /// Use the MIME driver with this `name` instead of the first usable one.
+    pub fn driver(mut self, name: impl Into<OsString>) -> Self {
+        self.driver = Some(name.into());
+        self
+    }
+
+    /// Use `drivers` instead of selecting one of the system,
+    /// e.g. with the output settings of an inspection.
+    pub fn drivers(mut self, drivers: DriverList) -> Self {
+        self.drivers = Some(drivers);
+        self
+    }
+
+    /// Only index files of the languages with these `names`.
+    pub fn languages<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.languages = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Classify with the tables of `classifier` instead of `LANGUAGES`.
+    pub fn classifier(mut self, classifier: ClassifierConfig) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Sniff files below `bytes` in-process instead of running the driver,
+    /// `SNIFF_SIZE` by default, see `DriverList::set_sniff_below()`.
+    pub fn sniff_below(mut self, bytes: u64) -> Self {
+        self.sniff_below = Some(bytes);
+        self
+    }
+
+    /// Keep up to `helpers` driver processes that serve all jobs, instead of
+    /// one process per file, see `DriverList::set_helpers()`.
+    pub fn driver_helpers(mut self, helpers: usize) -> Self {
+        self.helpers = Some(helpers);
+        self
+    }
+
+    /// Scheduling priority of the spawned drivers and indexers.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Run cscope in kernel mode, on by default.
+    pub fn kernel_mode(mut self, kernel_mode: bool) -> Self {
+        self.options.kernel_mode = kernel_mode;
+        self
+    }
+
+    /// Extra arguments for ctags.
+    pub fn ctags_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.options.ctags_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Name the databases, see `TagFileCreatorBuilder::name()`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Skip files last modified before `since`.
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.crawler.since = Some(since);
+        self
+    }
+
+    /// Skip the files `filter` skips, in addition to the excludes.
+    ///
+    /// Filters are asked in the order added.
+    pub fn filter(mut self, filter: impl PathFilter + 'static) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Read metadata, and directories if it keeps listings, through `cache`.
+    ///
+    /// Keep the cache across runs, e.g. of an `Indexer`, to read only the
+    /// directories that changed in between.
+    pub fn metadata_cache(mut self, cache: Arc<MetadataCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Send warnings to `reporter` instead of the console.
+    pub fn reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Pass each source file to `consumer`.
+    ///
+    /// With `backends(&[])`, no indexers are spawned and no databases created.
+    pub fn consumer(mut self, consumer: Arc<dyn Consumer>) -> Self {
+        self.consumer = Some(consumer);
+        self
+    }
+
+    /// Call `hooks` at the stages of each run.
+    ///
+    /// With `backends(&[])`, no indexers are spawned and no databases created.
+    pub fn hooks(mut self, hooks: Arc<dyn Hooks>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Write the paths to the databases and the consumer in `style`, as crawled by default.
+    pub fn path_style(mut self, style: PathStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Stop the run as soon as `cancel` is set, e.g. from another thread.
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Stop crawling as soon as `drain` is set, but classify and index the
+    /// files found so far and finish the databases.
+    pub fn drain(mut self, drain: Arc<AtomicBool>) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    /// Keep the incomplete databases of a cancelled run instead of removing them.
+    pub fn keep_partial(mut self, keep_partial: bool) -> Self {
+        self.keep_partial = keep_partial;
+        self
+    }
+
+    /// Check the settings and select the driver.
+    pub fn build(self) -> Result<Scope, Box<dyn Error>> {
+        if self.jobs == 0 || self.crawler.threads == 0 {
+            return Err("Scope needs at least one job and crawl thread.".into());
+        }
+        if self.options.backends.is_empty() && self.consumer.is_none() && self.hooks.is_none() {
+            return Err("Scope needs at least one backend, a consumer or hooks.".into());
+        }
+        for exclude in &self.crawler.excludes {
+            Pattern::exclude(exclude)?;
+        }
+        let mut driver = match self.drivers {
+            Some(drivers) => drivers,
+            None => DriverList::new(self.driver, false, self.priority)?,
+        };
+        if ! driver.usable() {
+            return Err("No usable driver found.".into());
+        }
+        let mut classifier = FileClassifier::new(self.classifier);
+        if let Some(ref languages) = self.languages {
+            classifier.set_languages(languages)?;
+        }
+        driver.set_reporter(Arc::clone(&self.reporter));
+        if let Some(bytes) = self.sniff_below {
+            driver.set_sniff_below(bytes);
+        }
+        if let Some(helpers) = self.helpers {
+            driver.set_helpers(helpers);
+        }
+
+        let mut crawler = self.crawler;
+        crawler.excludes.extend(DEFAULT_EXCLUDES.iter().map(|x| x.to_string()));
+        Ok(Scope {
+            roots: self.roots,
+            files: self.files,
+            crawler,
+            jobs: self.jobs,
+            driver: Arc::new(driver),
+            classifier,
+            priority: self.priority,
+            options: self.options,
+            name: self.name,
+            reporter: self.reporter,
+            consumer: self.consumer,
+            hooks: self.hooks,
+            style: self.style,
+            cancel: self.cancel,
+            drain: self.drain,
+            filters: self.filters,
+            cache: self.cache,
+            keep_partial: self.keep_partial,
+            expected: AtomicUsize::new(0),
+            current: Mutex::new((Weak::new(), Weak::new())),
+        })
     }
 }
 
-/// A collection of all available drivers.
+/// The whole pipeline: crawl the roots, classify the files and index the
+/// source files into ctags and cscope databases in the current directory.
 ///
-/// The collection implements Driver itself and exposes the best
-/// candidate to the user.
-#[derive(Debug, Clone)]
-pub struct DriverList {
-    drivers: Vec<GenericDriver>,
-    current: GenericDriver,
-    inspect: bool,
-}
-
-impl DriverList {
-    pub fn new(select: Option<OsString>, inspect: bool) -> Self {
-        let mut current: GenericDriver = XdgMimeDriver::new().into();
-        // Push order determines preference.
-        let drivers = vec![
-            current,
-            FileDriver::new().into(),
-            MimetypeDriver::new().into(),
-        ];
-        for d in drivers.iter() {
-            match select {
-                None => {
-                    if d.usable() {
-                        current = *d;
-                        break;
-                    }
-                }
-                Some(ref name) => {
-                    if d.name() == name {
-                        current = *d;
-                        break;
-                    }
-                }
-            }
+/// See the crate documentation for an example.
+#[derive(Debug)]
+pub struct Scope {
+    roots: Vec<PathBuf>,
+    /// Files to index instead of crawling the roots.
+    files: Option<Vec<PathBuf>>,
+    crawler: CrawlerOptions,
+    jobs: usize,
+    driver: Arc<DriverList>,
+    classifier: FileClassifier,
+    priority: Priority,
+    options: IndexerOptions,
+    name: Option<String>,
+    reporter: Arc<dyn Reporter>,
+    consumer: Option<Arc<dyn Consumer>>,
+    hooks: Option<Arc<dyn Hooks>>,
+    style: PathStyle,
+    cancel: Arc<AtomicBool>,
+    drain: Arc<AtomicBool>,
+    filters: Vec<Arc<dyn PathFilter>>,
+    cache: Option<Arc<MetadataCache>>,
+    keep_partial: bool,
+    /// Files to reserve the queue for, see `reserve()`.
+    expected: AtomicUsize,
+    /// The summary and the queue of the current run, see `summary()`.
+    current: Mutex<(Weak<Mutex<ScanSummary>>, Weak<FileQueue>)>,
+}
+
+impl Scope {
+    pub fn builder() -> ScopeBuilder {
+        ScopeBuilder {
+            roots: vec![PathBuf::from(".")],
+            files: None,
+            crawler: CrawlerOptions::default(),
+            jobs: 1,
+            driver: None,
+            drivers: None,
+            languages: None,
+            classifier: ClassifierConfig::default(),
+            priority: Priority::default(),
+            options: IndexerOptions::default(),
+            name: None,
+            reporter: Arc::new(Console),
+            consumer: None,
+            hooks: None,
+            style: PathStyle::default(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            drain: Arc::new(AtomicBool::new(false)),
+            filters: vec![],
+            cache: None,
+            sniff_below: None,
+            helpers: None,
+            keep_partial: false,
         }
+    }
+
+    /// Create the databases and summarize what went into them.
+    ///
+    /// A cancelled run stops the crawler, the workers and the indexers,
+    /// removes the incomplete databases unless they are kept,
+    /// and returns the partial summary. A drained run only stops the crawler.
+    pub fn run(&self) -> Result<ScanSummary, Box<dyn Error>> {
+        self.run_with(None)
+    }
+
+    /// Like `run()`, with an `Event` for each file and phase sent to `events`.
+    ///
+    /// The last event is either `Done` or `Failed`.
+    pub fn run_with_events(&self, events: mpsc::Sender<Event>) -> Result<ScanSummary, Box<dyn Error>> {
+        let result = self.run_with(Some(&events));
+        let event = match result {
+            Ok(ref summary) => Event::Done(summary.clone()),
+            Err(ref e) => Event::Failed(e.to_string()),
+        };
+        events.send(event).unwrap_or_default();
+        result
+    }
+
+    /// Create the databases on a new thread and receive the events.
+    ///
+    /// The last event is either `Done` or `Failed`.
+    pub fn events(self: Arc<Self>) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            self.run_with_events(sender).ok();
+        });
+        receiver
+    }
+
+    /// The counts of the current run so far, `None` between runs.
+    pub fn summary(&self) -> Option<ScanSummary> {
+        let summary = self.current.lock().unwrap().0.upgrade()?;
+        let summary = summary.lock().unwrap().clone();
+        Some(summary)
+    }
+
+    /// Number of files crawled but not classified yet in the current run.
+    pub fn queued(&self) -> usize {
+        self.current.lock().unwrap().1.upgrade().map_or(0, |files| files.len())
+    }
+
+    /// Count the files the crawler will queue, by crawling without classifying.
+    pub fn count(&self) -> Result<usize, Box<dyn Error>> {
+        match self.files {
+            Some(ref files) => Ok(files.len()),
+            None => self.crawler(Arc::new(self.file_queue(0))).count(),
+        }
+    }
+
+    /// Reserve the queue of the next runs for `files` files, e.g. from `count()`.
+    pub fn reserve(&self, files: usize) {
+        self.expected.store(files, Ordering::Relaxed);
+    }
 
-        DriverList { drivers, current, inspect, }
-    }
-
-    pub fn by_extension(&self, path: &Path) -> bool {
-        const EXTENSIONS: &[&str] = &[
-            "asm",
-            "c",
-            "cc",
-            "cpp",
-            "cs",
-            "cxx",
-            "erl",
-            "go",
-            "h",
-            "hpp",
-            "hxx",
-            "java",
-            "js",
-            "lua",
-            "php",
-            "pl",
-            "pm",
-            "py",
-            "rb",
-            "rs",
-            "s",
-            "sh",
-            "S",
-            "tcl",
-        ];
-
-        if let Some(ext) = path.extension() {
-            for e in EXTENSIONS.iter() {
-                if *e == ext.to_string_lossy() {
-                    return true;
+    /// The indexers for the settings of this scope.
+    pub(crate) fn tag_files(&self) -> TagFileCreatorBuilder {
+        let tag_files = TagFileCreator::builder()
+            .options(self.options.clone())
+            .priority(self.priority);
+        match self.name {
+            Some(ref name) => tag_files.name(name),
+            None => tag_files,
+        }
+    }
+
+    /// Queue the files with `crawler`, or the given ones.
+    pub(crate) fn crawl(
+        &self,
+        crawler: &FileCrawler,
+        queue: &FileQueue,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.files {
+            Some(ref files) => {
+                for file in files {
+                    queue.push(file.clone());
                 }
-            }
+                Ok(())
+            },
+            None => crawler.run().context("Crawl phase"),
         }
+    }
 
-        false
-    }
-
-    pub fn by_mime(&self, _path: &Path, mime: &str) -> bool {
-        const MIMETYPES: &[&str] = &[
-            // from shared-mime-info
-            "rust",
-            "x-c++",
-            "x-c++src",
-            "x-c++hdr",
-            "x-chdr",
-            "x-csharp",
-            "x-csrc",
-            "x-erlang",
-            "x-java",
-            "x-javascript",
-            "x-lua",
-            "x-perl",
-            "x-php",
-            "x-python",
-            "x-ruby",
-            "x-shellscript",
-            "x-tcl",
-            // from GNU file(1), where different
-            "x-c",
-        ];
-
-        for m in MIMETYPES.iter() {
-            if mime.ends_with(m) {
-                return true;
-            }
+    /// The queue between the crawler and `workers` workers.
+    pub(crate) fn file_queue(&self, workers: usize) -> FileQueue {
+        let mut files = FileQueue::new(self.crawler.schedule, workers);
+        if let Some(ref cache) = self.cache {
+            files = files.cache(Arc::clone(cache));
         }
+        let files = match self.crawler.queue_limit {
+            Some(limit) => files.spill_after(limit),
+            None => files,
+        };
+        files.reserve(self.expected.load(Ordering::Relaxed));
+        files
+    }
 
-        false
+    /// Start a run with `files` and `summary`, so that `summary()` and
+    /// `queued()` see them.
+    pub(crate) fn start(&self, files: &Arc<FileQueue>, summary: &Arc<Mutex<ScanSummary>>) {
+        *self.current.lock().unwrap() = (Arc::downgrade(summary), Arc::downgrade(files));
     }
 
-    pub fn inspect(&self,
-        reason: &str,
-        path: &Path,
-        mime: Option<&String>,
-        verbose: bool,
-    ) {
-        if verbose {
-            println!("{}", path.display());
-        } else if self.inspect {
-            if let Some(mime) = mime {
-                println!("{}: {:29} {}", reason, mime, path.display());
-            } else {
-                println!("{}: {:29} {}", reason, " ".to_string(), path.display());
-            }
+    /// A crawler that queues into `files`, with the settings of this scope.
+    pub(crate) fn crawler(&self, files: Arc<FileQueue>) -> FileCrawler {
+        let mut crawler = FileCrawler::with_options(self.roots.clone(), self.crawler.clone(), files);
+        crawler.set_cancel(Arc::clone(&self.cancel));
+        crawler.set_drain(Arc::clone(&self.drain));
+        for filter in &self.filters {
+            crawler.add_filter(Arc::clone(filter));
         }
+        if let Some(ref cache) = self.cache {
+            cache.advance(); // Each crawl sees the files anew
+            crawler.set_cache(Arc::clone(cache));
+        }
+        crawler
     }
-}
 
-impl Driver for DriverList {
-    #[inline]
-    fn name(&self) -> &str {
-        if self.usable() {
-            self.current.name()
-        } else {
-            "<none>"
+    /// The indexers, `None` without backends.
+    pub(crate) fn indexers(&self) -> Result<Option<TagFileCreator>, Box<dyn Error>> {
+        match self.options.backends.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(self.tag_files().spawn(self.reporter.as_ref()).context("Indexers")?)),
         }
     }
 
-    #[inline]
-    fn usable(&self) -> bool {
-        self.current.usable()
+    /// The classify stage for one file.
+    ///
+    /// Counts and reports files that are excluded, duplicates or fail, and
+    /// returns the source files for the write stage.
+    pub(crate) fn classify_file(
+        &self,
+        path: PathBuf,
+        summary: &Mutex<ScanSummary>,
+        events: Option<&mpsc::Sender<Event>>,
+    ) -> Option<Source> {
+        let emit = &mut |event| notify(events, event);
+        summary.lock().unwrap().crawled += 1;
+        let path = match self.recalled(path, summary, emit) {
+            Ok(source) => return self.deduplicate(source?, summary, emit),
+            Err(path) => path,
+        };
+        let start = Instant::now();
+        let mut driven = None;
+        let classification = self.classifier.classify_with(&path, || {
+            let start = Instant::now();
+            let mime = self.driver.run(&path);
+            driven = Some(start.elapsed());
+            mime
+        });
+        {
+            let mut summary = summary.lock().unwrap();
+            let driven = driven.inspect(|_| summary.driver_calls += 1).unwrap_or_default();
+            summary.driver_time += driven;
+            summary.rules_time += start.elapsed().saturating_sub(driven);
+        }
+        let source = self.classified(path, classification, summary, emit)?;
+        self.deduplicate(source, summary, emit)
     }
 
-    fn run(&self, path: &Path) -> Result<String, Box<dyn Error>> {
-        if self.usable() {
-            self.current.run(path)
-        } else {
-            Err("No usable driver found.".into())
+    /// The decision of an earlier run for `path`, or `path` back to classify it.
+    pub(crate) fn recalled(
+        &self,
+        path: PathBuf,
+        summary: &Mutex<ScanSummary>,
+        emit: &mut dyn FnMut(Event),
+    ) -> Result<Option<Source>, PathBuf> {
+        let Some(Recall { language, unchanged }) = self.hooks.as_ref().and_then(|h| h.recall(&path)) else {
+            return Err(path);
+        };
+        let rule = match unchanged {
+            true => {
+                summary.lock().unwrap().unchanged += 1;
+                "unchanged"
+            },
+            false => {
+                summary.lock().unwrap().resumed += 1;
+                "resume"
+            },
+        };
+        let shown = self.style.apply(&path).into_owned();
+        emit(Event::FileRecalled { path: path.clone(), language: language.clone() });
+        match language {
+            Some(language) => Ok(Some(Source { path, shown, language, rule, decision: None })),
+            None => {
+                self.decided(Decided {
+                    path: &path, shown: &shown, rule, reason: None, mime: None, language: None, index: None,
+                });
+                Ok(None)
+            },
         }
     }
-}
 
-impl fmt::Display for DriverList {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (i, d) in self.drivers.iter().enumerate() {
-            write!(f, "[{}] {}", i, d.name())?;
-            if ! d.usable() {
-                write!(f, " (!)")?;
-            } else if d.name() == self.current.name() {
-                write!(f, " (*)")?;
-            }
-            writeln!(f)?;
+    /// Count and report the `classification` of `path`, and return it if it is a source file.
+    pub(crate) fn classified(
+        &self,
+        path: PathBuf,
+        classification: Result<Classification, Box<dyn Error>>,
+        summary: &Mutex<ScanSummary>,
+        emit: &mut dyn FnMut(Event),
+    ) -> Option<Source> {
+        let shown = self.style.apply(&path).into_owned();
+        match classification {
+            Ok(Classification { decision: Decision::Excluded(exclusion), .. }) => {
+                summary.lock().unwrap().exclude_by(&path, &exclusion, &self.classifier.config().rules);
+                let reason = exclusion.reason();
+                self.decided(Decided {
+                    path: &path, shown: &shown, rule: reason.rule(), reason: Some(reason),
+                    mime: exclusion.mime(), language: None, index: None,
+                });
+                emit(Event::FileExcluded { path, mime: exclusion.mime().cloned(), reason });
+                None
+            },
+            Ok(Classification { decision, language }) => Some(Source {
+                path,
+                shown,
+                // Included files always have a language.
+                language: language.unwrap_or_default(),
+                rule: decision.reason().rule(),
+                decision: Some(decision),
+            }),
+            Err(e) => {
+                let message = format!("Cannot determine MIME type for {}: {}", path.display(), e);
+                self.reporter.warning(&message);
+                {
+                    let mut summary = summary.lock().unwrap();
+                    summary.errors += 1;
+                    summary.exclude("error", "no MIME type", 1);
+                }
+                self.decided(Decided {
+                    path: &path, shown: &shown, rule: "error", reason: None, mime: None, language: None, index: None,
+                });
+                emit(Event::FileError { path, message });
+                None
+            },
         }
-        Ok(())
     }
-}
 
+    /// `source`, unless it has the same content as an earlier one.
+    pub(crate) fn deduplicate(
+        &self,
+        source: Source,
+        summary: &Mutex<ScanSummary>,
+        emit: &mut dyn FnMut(Event),
+    ) -> Option<Source> {
+        let Some(original) = self.hooks.as_ref().and_then(|h| h.original(&source.path)) else {
+            return Some(source);
+        };
+        {
+            let mut summary = summary.lock().unwrap();
+            summary.duplicates += 1;
+            summary.exclude("duplicate", &self.style.apply(&original).display().to_string(), 1);
+        }
+        self.decided(Decided {
+            rule: "duplicate",
+            index: None,
+            ..source.decided()
+        });
+        emit(Event::FileDuplicate { path: source.path, original });
+        None
+    }
 
-/// File crawler that populates the list of files to scan.
-///
-/// After creation, feels like a std::thread.
-pub struct FileCrawler {
-    paths: Vec<PathBuf>,
-    excludes: Vec<String>,
-    files: Arc<Mutex<VecDeque<PathBuf>>>,
-}
+    /// Count and report `source` after the write stage, with the `error`
+    /// of the indexers if any, and return the event for it.
+    pub(crate) fn written(
+        &self,
+        source: Source,
+        indexed: bool,
+        error: Option<&str>,
+        summary: &Mutex<ScanSummary>,
+    ) -> Option<Event> {
+        {
+            let mut summary = summary.lock().unwrap();
+            if let Some(ref decision) = source.decision {
+                summary.include(decision);
+            }
+            summary.errors += usize::from(error.is_some());
+            summary.backend_errors += usize::from(error.is_some());
+        }
+        self.decided(Decided {
+            index: indexed.then(|| error.map_or(Ok(()), Err)),
+            ..source.decided()
+        });
+        match error {
+            None => {
+                let decision = source.decision?;
+                Some(Event::FileIncluded { path: source.path, mime: decision.mime().cloned(), rule: decision.reason() })
+            },
+            Some(e) => {
+                let message = format!("Cannot index {}: {}", source.shown.display(), e);
+                self.reporter.warning(&message);
+                Some(Event::BackendError { path: source.path, message })
+            },
+        }
+    }
 
-impl FileCrawler {
-    pub fn new(
-        paths: Vec<PathBuf>,
-        excludes: Vec<String>,
-        files: Arc<Mutex<VecDeque<PathBuf>>>,
-    ) -> Self {
-        FileCrawler { paths, excludes, files, }
+    /// Enter `phase`.
+    pub(crate) fn phase(&self, phase: Phase, events: Option<&mpsc::Sender<Event>>) {
+        if let Some(ref hooks) = self.hooks {
+            hooks.phase(phase);
+        }
+        notify(events, Event::PhaseChanged(phase));
     }
 
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        for path in &self.paths {
-            self.crawl(path)?;
-        };
-        Ok(())
+    fn decided(&self, decided: Decided) {
+        if let Some(ref hooks) = self.hooks {
+            hooks.decided(&decided);
+        }
     }
 
-    fn crawl(&self, path: &Path) -> Result<(), Box<dyn Error>> {
-        if path.exists() {
-            if self.excludes.iter().any(|x| {
-                path.display().to_string().contains(x)
-            }) {
-                return Ok(());
+    /// The write stage: feed the source files from `written` to the
+    /// indexers until the classify stage is done or the run is cancelled.
+    ///
+    /// Returns the indexers to finish or abort.
+    pub(crate) fn write_sources(
+        &self,
+        written: mpsc::Receiver<Source>,
+        mut tags_creator: Option<TagFileCreator>,
+        summary: &Mutex<ScanSummary>,
+        events: Option<&mpsc::Sender<Event>>,
+    ) -> Option<TagFileCreator> {
+        let mut batch = Vec::with_capacity(WRITE_BATCH);
+        while let Ok(source) = written.recv() {
+            if self.cancel.load(Ordering::Relaxed) {
+                break; // Abandon the channel
+            }
+            // Take what else is waiting, up to a batch.
+            batch.push(source);
+            batch.extend(written.try_iter().take(WRITE_BATCH - 1));
+            let start = Instant::now();
+            if let Some(ref consumer) = self.consumer {
+                for source in &batch {
+                    consumer.consume(&source.shown, source.mime());
+                }
             }
-            self.files.lock().unwrap().push_back(path.to_path_buf().clone());
-            if path.is_dir() {
-                for entry in fs::read_dir(path)? {
-                    let path = entry?.path();
-                    self.crawl(&path)?;
+            let result = match tags_creator {
+                Some(ref mut tags_creator) => tags_creator.write_batch(batch.iter().map(|s| s.shown.as_path())),
+                None => Ok(()),
+            };
+            summary.lock().unwrap().write_time += start.elapsed();
+            let error = result.err().map(|e| e.to_string());
+            for source in batch.drain(..) {
+                if let Some(event) = self.written(source, tags_creator.is_some(), error.as_deref(), summary) {
+                    notify(events, event);
                 }
             }
         }
-
-        Ok(())
+        tags_creator
     }
-}
 
+    /// Add the counts of `crawler` to `summary`, then finish the databases,
+    /// or abort the indexers of a cancelled run.
+    pub(crate) fn finish(
+        &self,
+        mut summary: ScanSummary,
+        crawler: &FileCrawler,
+        tags_creator: Option<TagFileCreator>,
+        events: Option<&mpsc::Sender<Event>>,
+    ) -> ScanSummary {
+        summary.excluded_pattern = crawler.excluded();
+        summary.drained = self.drain.load(Ordering::Relaxed);
+        summary.cscope_incremental = tags_creator.as_ref().and_then(TagFileCreator::cscope_incremental);
+        for (pattern, n) in crawler.exclusions() {
+            summary.exclude("pattern", &pattern, n);
+        }
 
-/// Tag file creator for Ctags and Cscope databases.
-///
-/// Create the tags databases for ctags and cscope in parallel
-/// for each file comming in from the `scanned_files` queue.
-pub struct TagFileCreator {
-    cscope: Option<Child>,
-    ctags: Option<Child>,
-}
-
-impl TagFileCreator {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let cscope = Command::new("cscope")
-            .args(["-bqki", "-"])
-            .stdin(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()
-            .ok();
-
-        let ctags = TagFileCreator::find_ctags()?
-            .args(["-L", "-", "--extra=+q", "--fields=+i"])
-            .stdin(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()
-            .ok();
-
-        if cscope.is_none() {
-            eprintln!("Cannot run cscope.");
-        }
-        if ctags.is_none() {
-            eprintln!("Cannot run Exuberant ctags.");
-        }
-        if ctags.is_none() && cscope.is_none() {
-            return Err("Cannot create any tag file database.".into());
-        }
-
-        Ok(TagFileCreator { cscope, ctags, })
-    }
-
-    /// Find a working Exuberant Ctags variant.
-    fn find_ctags() -> Result<Command, Box<dyn Error>> {
-        let mut ctags: Option<&str> = None;
-
-        for c in ["uctags", "ectags", "ctags"] {
-            if let Ok(out) = Command::new(c)
-                        .arg("--help")
-                        .stderr(Stdio::null())
-                        .output() {
-                let s = String::from_utf8(out.stdout)?;
-                if s.contains("Exuberant") {
-                    ctags = Some(c);
-                    break;
+        if self.cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            if let Some(tags_creator) = tags_creator {
+                tags_creator.abort(Duration::from_secs(2));
+                if ! self.keep_partial {
+                    for database in self.tag_files().databases() {
+                        fs::remove_file(database).unwrap_or_default();
+                    }
                 }
             }
-        };
-
-        match ctags {
-            Some(ctags) => Ok(Command::new(ctags)),
-            None        => Err("Cannot find Exuberant Ctags.".into()),
+            return summary;
         }
+
+        self.phase(Phase::Finalize, events);
+        let start = Instant::now();
+        drop(tags_creator); // Wait for the indexers.
+        summary.finalize_time = start.elapsed();
+        summary
     }
 
-    pub fn writeln(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
-        let mut write_vec: Vec<u8> = vec!();
-        let mut write: Box<&mut dyn Write> = Box::new(&mut write_vec);
-        writeln!(write, "{}", path.display())?;
+    /// Run the pipeline in three stages: the crawler queues files, `jobs`
+    /// workers classify them and one writer feeds the source files to the
+    /// indexers, so that driver processes and indexer pipes overlap.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "scope", level = "info", err,
+        skip_all, fields(roots = self.roots.len(), jobs = self.jobs)))]
+    fn run_with(&self, events: Option<&mpsc::Sender<Event>>) -> Result<ScanSummary, Box<dyn Error>> {
+        let files = Arc::new(self.file_queue(self.jobs));
+        let tags_creator = self.indexers()?;
+        let running = AtomicBool::new(true);
+        let summary = Arc::new(Mutex::new(ScanSummary::default()));
+        let crawler = self.crawler(Arc::clone(&files)); // Producer
+        self.start(&files, &summary);
 
-        if let Some(ref mut cscope) = self.cscope {
-            let cscope_stdin = cscope.stdin.as_mut().ok_or("Cscope died.")?;
-            cscope_stdin.write_all(write_vec.as_slice())?;
-        }
+        self.phase(Phase::Crawl, events);
+        #[cfg(feature = "tracing")]
+        let span = tracing::Span::current();
+        let start = Instant::now();
+        let (crawled, workers, tags_creator) = thread::scope(|s| {
+            let (sources, written) = mpsc::sync_channel(WRITE_BUFFER);
+            let writer = s.spawn(|| {
+                #[cfg(feature = "tracing")]
+                let _span = span.enter();
+                self.write_sources(written, tags_creator, &summary, events)
+            });
+            let workers: Vec<_> = (0..self.jobs).map(|id| {
+                let sources = sources.clone();
+                let (files, running, summary) = (&files, &running, &summary);
+                #[cfg(feature = "tracing")]
+                let span = &span;
+                s.spawn(move || {
+                    #[cfg(feature = "tracing")]
+                    let _span = span.enter();
+                    let hooks = self.hooks.as_deref().unwrap_or(&NoHooks);
+                    hooks.worker(id);
+                    let queue = files.worker(); // Consumer
+                    let started = Instant::now();
+                    let mut worker = WorkerSummary::default();
+                    let done = || self.cancel.load(Ordering::Relaxed)
+                        || (! running.load(Ordering::Relaxed) && files.is_empty());
+                    loop {
+                        if self.cancel.load(Ordering::Relaxed) {
+                            break; // Abandon the queue
+                        }
+                        let parked = Instant::now();
+                        if ! hooks.admit(id, &done) {
+                            break;
+                        }
+                        worker.wait(parked);
+                        // Check before popping, the crawler may add a last file in between.
+                        let crawled = ! running.load(Ordering::Relaxed);
+                        let Some(path) = queue.pop() else {
+                            hooks.working(id, None);
+                            if crawled {
+                                break;
+                            }
+                            let waiting = Instant::now();
+                            thread::sleep(IDLE_WAIT);
+                            worker.wait(waiting);
+                            continue;
+                        };
+                        worker.files += 1;
+                        hooks.working(id, Some(&path));
+                        let Some(source) = self.classify_file(path, summary, events) else {
+                            continue;
+                        };
+                        let mut source = Some(source);
+                        if ! hooks.send(&mut || source.take().is_some_and(|s| sources.send(s).is_ok())) {
+                            break; // The writer was cancelled
+                        }
+                    }
+                    worker.busy = started.elapsed().saturating_sub(worker.idle);
+                    worker
+                })
+            }).collect();
+            drop(sources);
+            let result = self.crawl(&crawler, &files);
+            running.store(false, Ordering::Relaxed);
+            let crawled = result.map(|_| start.elapsed());
+            let workers = workers.into_iter()
+                .map(|worker| worker.join().expect("Thread creation or execution failed."))
+                .collect();
+            let tags_creator = writer.join().expect("Thread creation or execution failed.");
+            crawled.map(|crawled| (crawled, workers, tags_creator))
+        })?;
+
+        let mut summary = summary.lock().unwrap().clone();
+        summary.workers = workers;
+        summary.crawl_time = crawled;
+        summary.classify_time = start.elapsed();
+        Ok(self.finish(summary, &crawler, tags_creator, events))
+    }
+}
 
-        if let Some(ref mut ctags) = self.ctags {
-            let ctags_stdin = ctags.stdin.as_mut().ok_or("Ctags died.")?;
-            ctags_stdin.write_all(write_vec.as_slice())?;
+/// A source file on its way from a worker to the writer.
+#[derive(Debug)]
+pub(crate) struct Source {
+    path: PathBuf,
+    /// The path as written to the databases.
+    shown: PathBuf,
+    language: String,
+    rule: &'static str,
+    /// `None` for a file an earlier run decided.
+    decision: Option<Decision>,
+}
+
+impl Source {
+    fn mime(&self) -> Option<&MimeType> {
+        self.decision.as_ref().and_then(Decision::mime)
+    }
+
+    /// What to tell `Hooks::decided()` about this source file.
+    fn decided(&self) -> Decided<'_> {
+        Decided {
+            path: &self.path,
+            shown: &self.shown,
+            rule: self.rule,
+            reason: self.decision.as_ref().map(Decision::reason),
+            mime: self.mime(),
+            language: Some(&self.language),
+            index: None,
         }
-        Ok(())
     }
 }
 
-/// Destructor for TagFileCreator.
+/// The default of each hook.
+struct NoHooks;
+
+impl Hooks for NoHooks {}
+
+/// Send `event` to `events`, if any.
 ///
-/// Close stdin for ctags and cscope and wait for their termination.
-impl Drop for TagFileCreator {
-    fn drop(&mut self) {
-        if let Some(ref mut cscope) = self.cscope {
-            let mut stdin = cscope.stdin.take().unwrap();
-            stdin.flush().unwrap_or_default();
-        }
-        if let Some(ref mut ctags) = self.ctags {
-            let mut stdin = ctags.stdin.take().unwrap();
-            stdin.flush().unwrap_or_default();
-        }
+/// A receiver that went away must not stop the run.
+pub(crate) fn notify(events: Option<&mpsc::Sender<Event>>, event: Event) {
+    if let Some(events) = events {
+        events.send(event).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if let Some(ref mut cscope) = self.cscope {
-            cscope.wait().unwrap_or_default();
+    #[test]
+    fn ionice_parses_classes_and_levels() {
+        let ionice = |class, level| Ok(IoNice { class, level });
+        assert_eq!("idle".parse(), ionice(IoClass::Idle, 4));
+        assert_eq!("best-effort".parse(), ionice(IoClass::BestEffort, 4));
+        assert_eq!("be:7".parse(), ionice(IoClass::BestEffort, 7));
+        assert_eq!("realtime:0".parse(), ionice(IoClass::Realtime, 0));
+        assert_eq!("rt:3".parse(), ionice(IoClass::Realtime, 3));
+    }
+
+    #[test]
+    fn ionice_rejects_unknown_classes_and_levels() {
+        for s in ["", "none", "Idle", "be:8", "be:-1", "be:", "rt:high", "realtime:1:2"] {
+            assert!(s.parse::<IoNice>().is_err(), "{}", s);
         }
-        if let Some(ref mut ctags) = self.ctags {
-            ctags.wait().unwrap_or_default();
+    }
+
+    #[test]
+    fn ionice_round_trips_through_strings() {
+        for s in ["idle", "best-effort:2", "realtime:0"] {
+            assert_eq!(s.parse::<IoNice>().map(String::from).as_deref(), Ok(s));
         }
+        let priority: Priority = serde_json::from_str(r#"{"nice": 10, "ionice": "be:7"}"#).unwrap();
+        assert_eq!(priority, Priority { nice: Some(10), ionice: Some(IoNice { class: IoClass::BestEffort, level: 7 }) });
+        assert!(serde_json::from_str::<Priority>(r#"{"ionice": "fast"}"#).is_err());
     }
 }
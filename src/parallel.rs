@@ -0,0 +1,72 @@
+//! The pipeline of `Scope` on a rayon pool.
+//!
+//! For applications that already manage a rayon pool: the files are
+//! classified with `par_iter()` on the pool instead of on `jobs` threads.
+//! The roots are crawled first, then all files are classified in parallel
+//! while one more thread writes the source files to the indexers.
+
+use std::error::Error;
+use std::iter;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::{ScanSummary, Scope, WRITE_BUFFER};
+
+impl Scope {
+    /// Like `run()`, with the classification on the current rayon pool.
+    ///
+    /// `jobs` is not used, the pool decides the parallelism.
+    pub fn run_rayon(&self) -> Result<ScanSummary, Box<dyn Error>> {
+        self.run_parallel(None)
+    }
+
+    /// Like `run_rayon()`, with the classification on `pool`.
+    pub fn run_in(&self, pool: &ThreadPool) -> Result<ScanSummary, Box<dyn Error>> {
+        self.run_parallel(Some(pool))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "scope", level = "info", err,
+        skip_all, fields(roots = self.roots.len())))]
+    fn run_parallel(&self, pool: Option<&ThreadPool>) -> Result<ScanSummary, Box<dyn Error>> {
+        let files = Arc::new(self.file_queue(1));
+        let tags_creator = self.indexers()?;
+        let summary = Arc::new(Mutex::new(ScanSummary::default()));
+        let crawler = self.crawler(Arc::clone(&files));
+        self.start(&files, &summary);
+
+        let start = Instant::now();
+        self.crawl(&crawler, &files)?;
+        let crawled = start.elapsed();
+        let queue = files.worker();
+        let files: Vec<_> = iter::from_fn(|| queue.pop()).collect();
+
+        let tags_creator = thread::scope(|s| {
+            let (sources, written) = mpsc::sync_channel(WRITE_BUFFER);
+            let summary = &summary;
+            let writer = s.spawn(move || self.write_sources(written, tags_creator, summary, None));
+            let classify = move || files.into_par_iter().for_each_with(sources, |sources, path| {
+                if self.cancel.load(Ordering::Relaxed) {
+                    return; // Abandon the files
+                }
+                if let Some(source) = self.classify_file(path, summary, None) {
+                    sources.send(source).unwrap_or_default();
+                }
+            });
+            match pool {
+                Some(pool) => pool.install(classify),
+                None => classify(),
+            }
+            writer.join().expect("Thread creation or execution failed.")
+        });
+
+        let mut summary = summary.lock().unwrap().clone();
+        summary.crawl_time = crawled;
+        summary.classify_time = start.elapsed();
+        Ok(self.finish(summary, &crawler, tags_creator, None))
+    }
+}
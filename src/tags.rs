@@ -0,0 +1,511 @@
+//! The cscope and ctags processes that create the databases.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, IoSlice, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, Priority, Reporter};
+
+/// Databases to create.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Cscope,
+    Ctags,
+}
+
+/// Options for the indexers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct IndexerOptions {
+    /// Run cscope in kernel mode, do not look into /usr/include.
+    pub kernel_mode: bool,
+    /// Extra arguments for ctags.
+    pub ctags_args: Vec<String>,
+    /// The databases to create, all by default.
+    pub backends: Vec<Backend>,
+    /// Directory for the databases, the current one by default.
+    pub directory: Option<PathBuf>,
+    /// The cscope binary, `cscope` from PATH by default.
+    pub cscope: Option<PathBuf>,
+    /// The ctags binary, the first Exuberant ctags from PATH by default.
+    pub ctags: Option<PathBuf>,
+    /// Extra arguments for cscope.
+    pub cscope_args: Vec<String>,
+}
+
+impl Default for IndexerOptions {
+    fn default() -> Self {
+        IndexerOptions { kernel_mode: true, ctags_args: vec![],
+            backends: vec![Backend::Cscope, Backend::Ctags], directory: None,
+            cscope: None, ctags: None, cscope_args: vec![], }
+    }
+}
+
+/// Names that Exuberant Ctags goes by, in the order they are tried.
+#[cfg(not(windows))]
+const CTAGS: &[&str] = &["uctags", "ectags", "ctags"];
+/// Names that Exuberant Ctags goes by, in the order they are tried.
+///
+/// The Windows builds of Universal Ctags are `ctags.exe`, some packages
+/// call it `universal-ctags.exe`.
+#[cfg(windows)]
+const CTAGS: &[&str] = &["ctags", "universal-ctags", "uctags", "ectags"];
+
+/// The program `name` from PATH.
+///
+/// On Windows, `where.exe` looks it up, and only an `.exe` is taken:
+/// batch files would parse their arguments by other quoting rules.
+#[cfg(windows)]
+fn locate(name: &str) -> Option<PathBuf> {
+    let out = Command::new("where.exe").arg(name).stderr(Stdio::null()).output().ok()?;
+    if ! out.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&out.stdout).lines()
+        .map(str::trim)
+        .find(|path| path.to_ascii_lowercase().ends_with(".exe"))
+        .map(PathBuf::from)
+}
+
+/// The program `name` from PATH, which `Command` searches itself.
+#[cfg(not(windows))]
+fn locate(name: &str) -> Option<PathBuf> {
+    Some(PathBuf::from(name))
+}
+
+/// The `program` to run, with `.exe` on Windows if it is a path without
+/// an extension that does not exist as given.
+///
+/// Bare names are left to the search of PATH, which adds `.exe` itself.
+fn executable(program: &Path) -> PathBuf {
+    if cfg!(windows) && program.extension().is_none() && program.components().count() > 1
+            && ! program.exists() {
+        return program.with_extension("exe");
+    }
+    program.to_path_buf()
+}
+
+/// The command of an indexer, or why it cannot run.
+pub(crate) type IndexerCommand = Result<Command, Box<dyn Error>>;
+
+/// Builder for `TagFileCreator`, with the command lines of the indexers.
+#[derive(Debug, Clone, Default)]
+pub struct TagFileCreatorBuilder {
+    options: IndexerOptions,
+    priority: Priority,
+    name: Option<String>,
+}
+
+impl TagFileCreatorBuilder {
+    /// Replace all indexer options.
+    pub fn options(mut self, options: IndexerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Create the databases in `directory` instead of the current one.
+    ///
+    /// The file names are passed as they are, so relative ones stay
+    /// relative to the current directory.
+    pub fn directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.options.directory = Some(directory.into());
+        self
+    }
+
+    /// The databases to create.
+    pub fn backends(mut self, backends: &[Backend]) -> Self {
+        self.options.backends = backends.to_vec();
+        self
+    }
+
+    /// Run `cscope` instead of the one from PATH.
+    pub fn cscope(mut self, cscope: impl Into<PathBuf>) -> Self {
+        self.options.cscope = Some(cscope.into());
+        self
+    }
+
+    /// Run `ctags` instead of searching PATH for Exuberant ctags.
+    pub fn ctags(mut self, ctags: impl Into<PathBuf>) -> Self {
+        self.options.ctags = Some(ctags.into());
+        self
+    }
+
+    /// Extra arguments for cscope.
+    pub fn cscope_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.options.cscope_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Extra arguments for ctags.
+    pub fn ctags_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.options.ctags_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Run cscope in kernel mode, the default.
+    pub fn kernel_mode(mut self, kernel_mode: bool) -> Self {
+        self.options.kernel_mode = kernel_mode;
+        self
+    }
+
+    /// Scheduling priority of the indexers.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Call the databases `cscope.<name>.out` and `tags.<name>`
+    /// instead of `cscope.out` and `tags`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// All files that make up the databases.
+    pub fn databases(&self) -> Vec<PathBuf> {
+        TagFileCreator::databases(self.name.as_deref()).into_iter()
+            .map(|database| self.path(database))
+            .collect()
+    }
+
+    /// `database` in the directory of the databases.
+    fn path(&self, database: impl Into<PathBuf>) -> PathBuf {
+        match self.options.directory {
+            Some(ref directory) => directory.join(database.into()),
+            None => database.into(),
+        }
+    }
+
+    /// The cscope database, the others are named after it.
+    fn cscope_out(&self) -> PathBuf {
+        self.path(match self.name {
+            Some(ref name) => format!("cscope.{}.out", name),
+            None => "cscope.out".to_string(),
+        })
+    }
+
+    /// The ctags database.
+    fn tags(&self) -> PathBuf {
+        self.path(match self.name {
+            Some(ref name) => format!("tags.{}", name),
+            None => "tags".to_string(),
+        })
+    }
+
+    /// Whether cscope updates its existing database instead of building it anew.
+    ///
+    /// It does if the database and its inverted index from `-q` exist, then
+    /// only the files that changed are parsed again. `None` without cscope.
+    pub fn cscope_incremental(&self) -> Option<bool> {
+        // The inverted index and the cross-reference come first.
+        self.options.backends.contains(&Backend::Cscope)
+            .then(|| self.databases().iter().take(3).all(|database| database.exists()))
+    }
+
+    /// The name and command of each indexer to run, reading file names from stdin.
+    pub(crate) fn commands(&self) -> Vec<(&'static str, IndexerCommand)> {
+        let mut commands = vec![];
+        if self.options.backends.contains(&Backend::Cscope) {
+            commands.push(("cscope", Ok(self.cscope_command())));
+        }
+        if self.options.backends.contains(&Backend::Ctags) {
+            commands.push(("Exuberant ctags", self.ctags_command()));
+        }
+        commands
+    }
+
+    /// The cscope command that reads file names from stdin.
+    fn cscope_command(&self) -> Command {
+        let mut cscope = Command::new(executable(self.options.cscope.as_deref().unwrap_or(Path::new("cscope"))));
+        if self.name.is_some() || self.options.directory.is_some() {
+            cscope.arg(format!("-f{}", self.cscope_out().display()));
+        }
+        self.priority.apply(&mut cscope)
+            .arg(if self.options.kernel_mode { "-bqki" } else { "-bqi" })
+            // A partial database is not worth updating.
+            .args((self.cscope_incremental() == Some(false)).then_some("-u"))
+            .args(&self.options.cscope_args)
+            .arg("-")
+            .stderr(Stdio::null());
+        cscope
+    }
+
+    /// The configured ctags program, or the one found.
+    fn ctags_program(&self) -> Result<Command, Box<dyn Error>> {
+        match self.options.ctags {
+            Some(ref ctags) => Ok(Command::new(executable(ctags))),
+            None => TagFileCreator::find_ctags(),
+        }
+    }
+
+    /// The ctags command that reads file names from stdin.
+    /// It gets no excludes, the crawler has already filtered the names.
+    fn ctags_command(&self) -> Result<Command, Box<dyn Error>> {
+        let mut ctags = self.ctags_program()?;
+        if self.name.is_some() || self.options.directory.is_some() {
+            // A separate argument keeps the path intact, whatever it contains.
+            ctags.arg("-f").arg(self.tags());
+        }
+        self.priority.apply(&mut ctags)
+            .args(["-L", "-", "--extra=+q", "--fields=+i"])
+            .args(&self.options.ctags_args)
+            .stderr(Stdio::null());
+        Ok(ctags)
+    }
+
+    /// Update the entries of `file` in the ctags database,
+    /// see `TagFileCreator::update()`.
+    pub(crate) fn update_tags(&self, file: &Path, include: bool) -> Result<(), Box<dyn Error>> {
+        self.update_in(&self.tags(), file, include)
+    }
+
+    fn update_in(&self, tags: &Path, file: &Path, include: bool) -> Result<(), Box<dyn Error>> {
+        let strip = |p: &str| p.strip_prefix("./").unwrap_or(p).to_string();
+        let name = strip(&file.to_string_lossy());
+
+        if tags.exists() {
+            let content = fs::read_to_string(tags)?;
+            let mut kept = String::with_capacity(content.len());
+            for line in content.lines() {
+                if line.starts_with("!_TAG_")
+                    || line.split('\t').nth(1).map(strip) != Some(name.clone()) {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+            }
+            let mut tmp = tags.as_os_str().to_owned();
+            tmp.push(".tmp");
+            fs::write(&tmp, kept)?;
+            fs::rename(&tmp, tags)?;
+        }
+
+        if include && file.exists() {
+            let mut ctags = self.ctags_program()?;
+            let status = self.priority.apply(&mut ctags)
+                .arg("-a")
+                .arg("-f")
+                .arg(tags)
+                .args(["--extra=+q", "--fields=+i"])
+                .args(&self.options.ctags_args)
+                .arg(file)
+                .stderr(Stdio::null())
+                .status()?;
+            if ! status.success() {
+                return Err(format!("Ctags failed with {}.", status).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spawn the indexers of the `backends`.
+    ///
+    /// An indexer that does not start is a warning to the `reporter`,
+    /// it is an error only if none can run or no Exuberant ctags is found.
+    pub fn spawn(&self, reporter: &dyn Reporter) -> Result<TagFileCreator, Box<dyn Error>> {
+        let incremental = self.cscope_incremental();
+        let mut writers = vec![];
+        let mut names = vec![];
+        let mut children = vec![];
+        for (tool, command) in self.commands() {
+            match command?.stdin(Stdio::piped()).spawn() {
+                Ok(mut child) => {
+                    if let Some(stdin) = child.stdin.take() {
+                        writers.push(stdin);
+                        names.push(tool.to_string());
+                    }
+                    children.push(child);
+                },
+                Err(_) => reporter.warning(&format!("Cannot run {}.", tool)),
+            }
+        }
+
+        if children.is_empty() {
+            return Err("Cannot create any tag file database.".into());
+        }
+
+        Ok(TagFileCreator { writers, names, children, incremental })
+    }
+}
+
+/// Tag file creator for Ctags and Cscope databases.
+///
+/// Create the tags databases for ctags and cscope in parallel
+/// for each file comming in from the `scanned_files` queue.
+/// By default the file names go to the stdin of the indexers from
+/// `TagFileCreator::builder()`,
+/// any other `Write` sinks can be used with `with_writers()`.
+pub struct TagFileCreator<W: Write = ChildStdin> {
+    writers: Vec<W>,
+    /// The indexer behind each writer, for errors.
+    names: Vec<String>,
+    children: Vec<Child>,
+    incremental: Option<bool>,
+}
+
+impl TagFileCreator {
+    /// Configure the indexers, see `TagFileCreatorBuilder::spawn()`.
+    pub fn builder() -> TagFileCreatorBuilder {
+        TagFileCreatorBuilder::default()
+    }
+
+    /// All files that make up the databases with an optional `name`.
+    pub fn databases(name: Option<&str>) -> Vec<PathBuf> {
+        match name {
+            Some(name) => {
+                let cscope = format!("cscope.{}.out", name);
+                vec![
+                    PathBuf::from(format!("{}.in", cscope)),
+                    PathBuf::from(format!("{}.po", cscope)),
+                    PathBuf::from(cscope),
+                    PathBuf::from(format!("tags.{}", name)),
+                ]
+            },
+            None => vec![
+                PathBuf::from("cscope.in.out"),
+                PathBuf::from("cscope.po.out"),
+                PathBuf::from("cscope.out"),
+                PathBuf::from("tags"),
+            ],
+        }
+    }
+
+    /// Update the entries of a single `file` in an existing ctags `tags` file.
+    ///
+    /// This is what editor plugins like vim-gutentags expect after a file
+    /// has been written: Drop all stale entries for `file`, then append
+    /// fresh ones if `include` is set.
+    pub fn update(
+        priority: Priority,
+        tags: &Path,
+        file: &Path,
+        include: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        TagFileCreator::builder().priority(priority).update_in(tags, file, include)
+    }
+
+    /// Find a working Exuberant Ctags variant.
+    ///
+    /// Universal Ctags counts, its help names Exuberant Ctags as well.
+    fn find_ctags() -> Result<Command, Box<dyn Error>> {
+        for c in CTAGS {
+            let Some(ctags) = locate(c) else {
+                continue;
+            };
+            if let Ok(out) = Command::new(&ctags)
+                        .arg("--help")
+                        .stderr(Stdio::null())
+                        .output() {
+                if String::from_utf8_lossy(&out.stdout).contains("Exuberant") {
+                    return Ok(Command::new(ctags));
+                }
+            }
+        }
+        Err("Cannot find Exuberant Ctags.".into())
+    }
+
+    /// Stop creating the databases.
+    ///
+    /// Close stdin for ctags and cscope and give them `timeout` to terminate.
+    /// Kill them afterwards. The databases are most likely incomplete.
+    pub fn abort(mut self, timeout: Duration) {
+        self.writers.clear();
+
+        let deadline = Instant::now() + timeout;
+        for child in self.children.iter_mut() {
+            while let Ok(None) = child.try_wait() {
+                if Instant::now() >= deadline {
+                    child.kill().unwrap_or_default();
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+impl<W: Write> TagFileCreator<W> {
+    /// Write the file names to `writers` instead of indexers,
+    /// e.g. to capture the raw file list.
+    pub fn with_writers(writers: Vec<W>) -> Self {
+        let names = (1..=writers.len()).map(|i| format!("writer {}", i)).collect();
+        TagFileCreator { writers, names, children: vec![], incremental: None }
+    }
+
+    /// Whether cscope updates its existing database, see
+    /// `TagFileCreatorBuilder::cscope_incremental()`.
+    pub fn cscope_incremental(&self) -> Option<bool> {
+        self.incremental
+    }
+
+    /// Flush the writers and hand them back.
+    pub fn into_writers(mut self) -> Result<Vec<W>, Box<dyn Error>> {
+        for writer in self.writers.iter_mut() {
+            writer.flush()?;
+        }
+        Ok(std::mem::take(&mut self.writers))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", err, skip_all,
+        fields(path = %path.display())))]
+    pub fn writeln(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.write_batch([path])
+    }
+
+    /// Like `writeln()` for each of `paths`, with a single vectored write to
+    /// each indexer, so that the paths are not copied into one buffer first.
+    ///
+    /// Writers without vectored writes get one write per path and newline.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", err, skip_all))]
+    pub fn write_batch<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) -> Result<(), Box<dyn Error>> {
+        let lines = line_slices(paths);
+        for (writer, name) in self.writers.iter_mut().zip(&self.names) {
+            write_all_vectored(writer, &mut lines.clone())
+                .with_context(|| format!("Cannot write to {}", name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Each of `paths` as is and a newline, the indexers take any bytes but newlines.
+pub(crate) fn line_slices<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Vec<IoSlice<'a>> {
+    paths.into_iter()
+        .flat_map(|path| [IoSlice::new(path.as_os_str().as_encoded_bytes()), IoSlice::new(b"\n")])
+        .collect()
+}
+
+/// Write all of `slices` to `writer`, like the unstable `Write::write_all_vectored()`.
+///
+/// A pipe takes up to `IOV_MAX` slices with one call.
+fn write_all_vectored(writer: &mut impl Write, mut slices: &mut [IoSlice]) -> io::Result<()> {
+    while ! slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Destructor for TagFileCreator.
+///
+/// Close stdin for ctags and cscope and wait for their termination.
+impl<W: Write> Drop for TagFileCreator<W> {
+    fn drop(&mut self) {
+        for mut writer in self.writers.drain(..) {
+            writer.flush().unwrap_or_default();
+        }
+        for child in self.children.iter_mut() {
+            child.wait().unwrap_or_default();
+        }
+    }
+}
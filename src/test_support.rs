@@ -0,0 +1,171 @@
+//! Stand-ins for the drivers and indexers, to test pipelines without
+//! `file`, `xdg-mime`, cscope or ctags installed.
+
+use std::collections::HashMap;
+use std::error::Error;
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "async")]
+use std::process::Command;
+
+use crate::{Consumer, Driver, DriverList, Format, MimeType, ScopeBuilder};
+use crate::drivers::GenericDriver;
+
+/// A driver with scripted MIME types.
+///
+/// Paths are looked up without a leading `./`, as the crawler reports them.
+/// Clones share the number of calls.
+#[derive(Debug, Clone, Default)]
+pub struct MockDriver {
+    responses: HashMap<PathBuf, Result<MimeType, String>>,
+    fallback: Option<MimeType>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl MockDriver {
+    /// A driver without any responses, it fails for every path.
+    pub fn new() -> Self {
+        MockDriver::default()
+    }
+
+    fn key(path: &Path) -> PathBuf {
+        path.strip_prefix(".").unwrap_or(path).to_path_buf()
+    }
+
+    /// Report `mime` for `path`.
+    ///
+    /// Panics if `mime` is no valid MIME type.
+    pub fn respond(mut self, path: impl AsRef<Path>, mime: &str) -> Self {
+        let mime = mime.parse().unwrap_or_else(|e| panic!("{}", e));
+        self.responses.insert(MockDriver::key(path.as_ref()), Ok(mime));
+        self
+    }
+
+    /// Fail with `message` for `path`.
+    pub fn fail(mut self, path: impl AsRef<Path>, message: &str) -> Self {
+        self.responses.insert(MockDriver::key(path.as_ref()), Err(message.to_string()));
+        self
+    }
+
+    /// Report `mime` for all paths without a response.
+    ///
+    /// Panics if `mime` is no valid MIME type.
+    pub fn fallback(mut self, mime: &str) -> Self {
+        self.fallback = Some(mime.parse().unwrap_or_else(|e| panic!("{}", e)));
+        self
+    }
+
+    /// Number of paths the driver was asked for.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    fn response(&self, path: &Path) -> Result<MimeType, String> {
+        match self.responses.get(&MockDriver::key(path)) {
+            Some(response) => response.clone(),
+            None => self.fallback.clone()
+                .ok_or_else(|| format!("No response for {}.", path.display())),
+        }
+    }
+
+    /// A command that prints the response, for the async pipeline.
+    #[cfg(feature = "async")]
+    pub(crate) fn command(&self, path: &Path) -> Command {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        match self.response(path) {
+            Ok(mime) => {
+                let mut cmd = Command::new("echo");
+                cmd.arg(mime.to_string());
+                cmd
+            },
+            Err(_) => Command::new("false"),
+        }
+    }
+}
+
+impl Driver for MockDriver {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn usable(&self) -> bool {
+        true
+    }
+
+    fn run(&self, path: &Path) -> Result<MimeType, Box<dyn Error>> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Ok(self.response(path)?)
+    }
+}
+
+impl DriverList {
+    /// A list with only the `driver`.
+    pub fn mock(driver: MockDriver, inspect: bool) -> Self {
+        let driver = GenericDriver::MockDriver(driver);
+        DriverList { drivers: vec![driver.clone()], current: driver, inspect,
+            format: Format::Text, print0: false, color: false, show: None,
+            reporter: Arc::new(crate::Console), sniff_below: 0, helpers: None, }
+    }
+}
+
+impl ScopeBuilder {
+    /// Classify with `driver` instead of the drivers of the system.
+    pub fn mock_driver(self, driver: MockDriver) -> Self {
+        self.drivers(DriverList::mock(driver, false))
+    }
+}
+
+/// An in-memory stand-in for the indexers that collects the file names.
+///
+/// Use it as a `Write` sink for `TagFileCreator::with_writers()`, or as the
+/// `Consumer` of a `Scope` without backends. Clones share the file names.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    /// All file names so far, one per line written.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.data.lock().unwrap()
+            .split(|b| *b == b'\n')
+            .filter(|line| ! line.is_empty())
+            .map(|line| {
+                #[cfg(unix)]
+                return PathBuf::from(OsStr::from_bytes(line));
+                #[cfg(not(unix))]
+                PathBuf::from(String::from_utf8_lossy(line).into_owned())
+            })
+            .collect()
+    }
+}
+
+impl Write for MemoryBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Consumer for MemoryBackend {
+    fn consume(&self, path: &Path, _mime: Option<&MimeType>) {
+        let mut data = self.data.lock().unwrap();
+        data.extend_from_slice(path.as_os_str().as_encoded_bytes());
+        data.push(b'\n');
+    }
+}
@@ -0,0 +1,82 @@
+//! Batched system calls on io_uring, for the crawler.
+//!
+//! Each thread has a ring of its own. Kernels without io_uring, or sandboxes
+//! that forbid it, make the callers fall back to one system call per file.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::io;
+use std::mem::{self, MaybeUninit};
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+use io_uring::{opcode, types, IoUring};
+
+/// Submissions per system call.
+const ENTRIES: u32 = 64;
+
+thread_local! {
+    /// The ring of the thread once asked for, `None` if the kernel has none.
+    static RING: RefCell<Option<Option<IoUring>>> = const { RefCell::new(None) };
+}
+
+/// The modes of the `names` in `dir`, following symbolic links.
+///
+/// Returns `None` without io_uring, the caller stats the names itself then.
+pub(crate) fn stat_modes(dir: BorrowedFd, names: &[&CStr]) -> Option<Vec<io::Result<u32>>> {
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        let uring = ring.get_or_insert_with(|| IoUring::new(ENTRIES).ok()).as_mut()?;
+        let mut modes = Vec::with_capacity(names.len());
+        for chunk in names.chunks(ENTRIES as usize) {
+            match stat_chunk(uring, dir, chunk) {
+                Some(chunk) => modes.extend(chunk),
+                None => {
+                    // Do not try a ring that failed again.
+                    *ring = Some(None);
+                    return None;
+                },
+            }
+        }
+        Some(modes)
+    })
+}
+
+/// Stat up to `ENTRIES` names with one submission.
+fn stat_chunk(ring: &mut IoUring, dir: BorrowedFd, names: &[&CStr]) -> Option<Vec<io::Result<u32>>> {
+    let mut buffers: Vec<MaybeUninit<libc::statx>> = (0..names.len()).map(|_| MaybeUninit::zeroed()).collect();
+    {
+        let mut submission = ring.submission();
+        for (i, name) in names.iter().enumerate() {
+            let statx = buffers[i].as_mut_ptr().cast::<types::statx>();
+            let entry = opcode::Statx::new(types::Fd(dir.as_raw_fd()), name.as_ptr(), statx)
+                .mask(libc::STATX_TYPE)
+                .build()
+                .user_data(i as u64);
+            // Safe, the names and buffers outlive the completions.
+            unsafe { submission.push(&entry) }.ok()?;
+        }
+    }
+    let mut results: Vec<Option<io::Result<u32>>> = (0..names.len()).map(|_| None).collect();
+    let mut pending = names.len();
+    while pending > 0 {
+        match ring.submit_and_wait(pending) {
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => {
+                // The kernel may still write to the buffers.
+                mem::forget(buffers);
+                return None;
+            },
+        }
+        for completion in ring.completion() {
+            let i = completion.user_data() as usize;
+            results[i] = Some(match completion.result() {
+                errno if errno < 0 => Err(io::Error::from_raw_os_error(-errno)),
+                // Safe, the kernel filled in the buffer.
+                _ => Ok(u32::from(unsafe { buffers[i].assume_init_ref() }.stx_mode)),
+            });
+            pending -= 1;
+        }
+    }
+    Some(results.into_iter().map(Option::unwrap).collect())
+}
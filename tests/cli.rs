@@ -0,0 +1,153 @@
+//! Runs of the scope binary, with stand-ins for `file`, cscope and ctags.
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+/// The stand-ins: `file` tells directories from data, the indexers take their input.
+const TOOLS: &[(&str, &str)] = &[
+    ("file", r#"#!/bin/sh
+if [ "$1" = "-h" ]; then echo "usage: file --mime-type" >&2; exit 0; fi
+kind() { if [ -d "$1" ]; then echo inode/directory; else echo application/octet-stream; fi; }
+if [ "$1" = "-n" ]; then
+    while IFS= read -r f; do kind "$f"; done
+    exit 0
+fi
+kind "$3"
+"#),
+    ("cscope", "#!/bin/sh\ncat > cscope.files\n"),
+    ("ctags", r#"#!/bin/sh
+if [ "$1" = "--help" ]; then echo "Exuberant Ctags"; exit 0; fi
+echo "$@" > ctags.args
+cat > ctags.files
+"#),
+];
+
+/// A scratch directory with the stand-ins in `bin` and the sources in `src`.
+struct Tree(PathBuf);
+
+impl Tree {
+    fn new(name: &str) -> Self {
+        let root = env::temp_dir().join(format!("scope-{}-{}", name, process::id()));
+        fs::remove_dir_all(&root).unwrap_or_default();
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        for (tool, script) in TOOLS {
+            let path = root.join("bin").join(tool);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        Tree(root)
+    }
+
+    /// Create the source `file` with `content`.
+    fn file(&self, file: &str, content: &str) -> &Self {
+        let path = self.0.join("src").join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Scope in `src` with `args`, which start with the subcommand if any.
+    fn command(&self, args: &[&str]) -> Command {
+        let path = format!("{}:{}", self.0.join("bin").display(), env::var("PATH").unwrap_or_default());
+        let mut command = Command::new(env!("CARGO_BIN_EXE_scope"));
+        command.args(args).current_dir(self.0.join("src")).env("PATH", path);
+        command
+    }
+
+    /// Run scope like `command()`, without a `.scope.toml`.
+    fn scope(&self, args: &[&str]) -> Output {
+        self.command(args).arg("--no-config").output().unwrap()
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).unwrap_or_default();
+    }
+}
+
+/// The value of the `field` of the last progress event in `events`.
+fn last_event(events: &str, field: &str) -> serde_json::Value {
+    let last = events.lines().rfind(|line| line.contains(r#""event":"progress""#)).unwrap();
+    serde_json::from_str::<serde_json::Value>(last).unwrap()[field].clone()
+}
+
+#[test]
+fn dedup_with_state_counts_reused_duplicates() {
+    let tree = Tree::new("dedup-state");
+    tree.file("a.c", "int a;\n").file("vendor/a.c", "int a;\n").file("b.c", "int b;\n");
+    for run in ["first", "second"] {
+        let out = tree.scope(&["--state", "--dedup", "--progress-json", "../events.json"]);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(out.status.success(), "{} run failed: {}", run, stderr);
+        assert!(stderr.lines().any(|line| line.split_whitespace().eq(["Duplicates:", "1"])), "{}", stderr);
+        let events = fs::read_to_string(tree.0.join("events.json")).unwrap();
+        let included = if run == "first" { 2 } else { 0 };
+        assert_eq!(last_event(&events, "included"), included, "{} run", run);
+        let indexed = fs::read_to_string(tree.0.join("src/cscope.files")).unwrap();
+        assert_eq!(indexed.lines().count(), 2, "{} run: {}", run, indexed);
+    }
+}
+
+#[test]
+fn ctags_gets_the_crawled_files() {
+    let tree = Tree::new("excludes");
+    tree.file("a.c", "int a;\n").file("gen/b.c", "int b;\n").file("gen/keep/c.c", "int c;\n");
+    let out = tree.scope(&["-x", "gen", "-x", "!gen/keep"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let tagged = fs::read_to_string(tree.0.join("src/ctags.files")).unwrap();
+    let mut tagged = tagged.lines().collect::<Vec<_>>();
+    tagged.sort();
+    assert_eq!(tagged, ["./a.c", "./gen/keep/c.c"]);
+    let args = fs::read_to_string(tree.0.join("src/ctags.args")).unwrap();
+    assert!(! args.contains("--exclude"), "{}", args);
+}
+
+#[test]
+fn inspect_leaves_databases_alone() {
+    let tree = Tree::new("inspect");
+    tree.file("a.c", "int a;\n");
+    let out = tree.scope(&["inspect"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(out.status.success(), "{}", stderr);
+    assert!(! stderr.contains("Cscope database"), "{}", stderr);
+    for database in ["cscope.files", "ctags.files", "cscope.out", "tags"] {
+        assert!(! tree.0.join("src").join(database).exists(), "{} was written", database);
+    }
+}
+
+#[test]
+fn languages_are_configured() {
+    let tree = Tree::new("languages");
+    tree.file(".scope.toml", "[extensions]\nkotlin = [\"kt\"]\nc = [\"c\"]\n");
+    let out = tree.command(&["languages"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let extensions = |language: &str| stdout.lines()
+        .find(|line| line.split_whitespace().next() == Some(language))
+        .map(|line| line.split_whitespace().filter(|w| w.starts_with('.')).collect::<Vec<_>>());
+    assert_eq!(extensions("kotlin"), Some(vec![".kt"]), "{}", stdout);
+    assert_eq!(extensions("c"), Some(vec![".c"]), "{}", stdout);
+
+    let out = tree.command(&["languages", "--languages", "kotlin"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "kotlin       .kt");
+}
+
+#[test]
+fn update_decides_like_build() {
+    let tree = Tree::new("update");
+    tree.file("a.c", "int a;\n").file("vendor/b.c", "int b;\n").file("tags", "");
+    let args = tree.0.join("src/ctags.args");
+    let out = tree.scope(&["update", "-x", "vendor", "vendor/b.c"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(! args.exists(), "excluded file was tagged");
+
+    let out = tree.scope(&["update", "-x", "vendor", "a.c"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(fs::read_to_string(&args).unwrap().ends_with(" a.c\n"));
+}
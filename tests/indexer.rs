@@ -0,0 +1,43 @@
+//! Updates of the databases with `Indexer`, cscope is a script that keeps its input.
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process;
+
+use scope_rs::test_support::MockDriver;
+use scope_rs::{Backend, Indexer, IndexerOptions, Scope};
+
+#[test]
+fn update_writes_paths_as_crawled() {
+    let root = env::temp_dir().join(format!("scope-indexer-{}", process::id()));
+    fs::remove_dir_all(&root).unwrap_or_default();
+    fs::create_dir_all(root.join("src")).unwrap();
+    let cscope = root.join("cscope");
+    fs::write(&cscope, "#!/bin/sh\ncat > \"$0.files\"\n").unwrap();
+    fs::set_permissions(&cscope, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(root.join("src/a.c"), "int a;\n").unwrap();
+    // The crawler reports paths below `.` with a leading `./`.
+    env::set_current_dir(&root).unwrap();
+
+    let options = IndexerOptions { cscope: Some(cscope.clone()), ..IndexerOptions::default() };
+    let builder = Scope::builder()
+        .roots(["./src"])
+        .indexer_options(options)
+        .backends(&[Backend::Cscope])
+        .mock_driver(MockDriver::new().fallback("application/octet-stream"));
+    let mut indexer = Indexer::new(builder).unwrap();
+    let written = || fs::read_to_string(root.join("cscope.files")).unwrap();
+    assert_eq!(written(), "./src/a.c\n");
+
+    fs::write(root.join("src/b.c"), "int b;\n").unwrap();
+    assert!(indexer.add("src/b.c".as_ref()).unwrap());
+    assert_eq!(written(), "./src/a.c\n./src/b.c\n");
+    assert!(indexer.refresh("./src/a.c".as_ref()).unwrap());
+    assert_eq!(written(), "./src/a.c\n./src/b.c\n");
+    assert!(indexer.contains("src/a.c".as_ref()));
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&root).unwrap_or_default();
+}
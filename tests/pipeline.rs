@@ -0,0 +1,115 @@
+//! Runs of a `Scope` with a scripted driver and an in-memory backend.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{mpsc, Arc, Mutex};
+
+use scope_rs::test_support::{MemoryBackend, MockDriver};
+use scope_rs::{Decided, Event, Hooks, Recall, Scope};
+
+#[test]
+fn scope_includes_and_excludes() {
+    let root = env::temp_dir().join(format!("scope-pipeline-{}", process::id()));
+    fs::remove_dir_all(&root).unwrap_or_default();
+    for (file, content) in [
+        ("src/main.c", "int main(void) { return 0; }\n"),
+        ("tools/build", "#!/bin/sh\nmake\n"),
+        ("README", "Read me.\n"),
+        ("logo.png", "\u{89}PNG\n"),
+    ] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    let driver = MockDriver::new()
+        .respond(root.join("tools/build"), "text/x-shellscript")
+        .fallback("text/plain");
+    let backend = MemoryBackend::new();
+    let scope = Scope::builder()
+        .roots([&root])
+        .backends(&[])
+        .mock_driver(driver.clone())
+        .consumer(Arc::new(backend.clone()))
+        .build()
+        .unwrap();
+    let (events, received) = mpsc::channel();
+    let summary = scope.run_with_events(events).unwrap();
+
+    let included: BTreeSet<PathBuf> = backend.files().into_iter().collect();
+    assert_eq!(included, BTreeSet::from([root.join("src/main.c"), root.join("tools/build")]));
+    let excluded: BTreeSet<PathBuf> = received.try_iter()
+        .filter_map(|event| match event {
+            Event::FileExcluded { path, .. } => Some(path),
+            _ => None,
+        })
+        .filter(|path| path.is_file())
+        .collect();
+    assert_eq!(excluded, BTreeSet::from([root.join("README"), root.join("logo.png")]));
+
+    assert_eq!((summary.by_extension, summary.by_mime, summary.by_rule), (1, 1, 0));
+    assert_eq!((summary.excluded_mime, summary.excluded_binary), (summary.crawled - 3, 1));
+    // Neither the extension nor the binary extension needs the driver.
+    assert_eq!(driver.calls(), summary.crawled - 2);
+
+    fs::remove_dir_all(&root).unwrap_or_default();
+}
+
+/// Recalls `known.c` as C, makes `copy.c` a duplicate of `main.c` and
+/// records the rule of each decision.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(PathBuf, &'static str)>>);
+
+impl Hooks for Recorder {
+    fn recall(&self, path: &Path) -> Option<Recall> {
+        path.ends_with("known.c").then(|| Recall { language: Some("c".to_string()), unchanged: true })
+    }
+
+    fn original(&self, path: &Path) -> Option<PathBuf> {
+        path.ends_with("copy.c").then(|| path.with_file_name("main.c"))
+    }
+
+    fn decided(&self, decided: &Decided) {
+        self.0.lock().unwrap().push((decided.path.to_path_buf(), decided.rule));
+    }
+}
+
+#[test]
+fn scope_calls_hooks() {
+    let root = env::temp_dir().join(format!("scope-hooks-{}", process::id()));
+    fs::remove_dir_all(&root).unwrap_or_default();
+    fs::create_dir_all(&root).unwrap();
+    for file in ["main.c", "copy.c", "known.c"] {
+        fs::write(root.join(file), "int x;\n").unwrap();
+    }
+
+    let driver = MockDriver::new().fallback("inode/directory");
+    let backend = MemoryBackend::new();
+    let hooks = Arc::new(Recorder::default());
+    let scope = Scope::builder()
+        .roots([&root])
+        .backends(&[])
+        .mock_driver(driver.clone())
+        .consumer(Arc::new(backend.clone()))
+        .hooks(hooks.clone())
+        .build()
+        .unwrap();
+    let summary = scope.run().unwrap();
+
+    let included: BTreeSet<PathBuf> = backend.files().into_iter().collect();
+    assert_eq!(included, BTreeSet::from([root.join("main.c"), root.join("known.c")]));
+    let decided: BTreeSet<_> = hooks.0.lock().unwrap().iter().cloned().collect();
+    assert_eq!(decided, BTreeSet::from([
+        (root.clone(), "mime"),
+        (root.join("main.c"), "extension"),
+        (root.join("copy.c"), "duplicate"),
+        (root.join("known.c"), "unchanged"),
+    ]));
+    assert_eq!((summary.by_extension, summary.unchanged, summary.duplicates), (1, 1, 1));
+    assert!(scope.summary().is_none());
+
+    fs::remove_dir_all(&root).unwrap_or_default();
+}